@@ -0,0 +1,56 @@
+use criterion::{criterion_group, criterion_main, Criterion};
+use quizcrawler::crawler::data::Crawler;
+use std::fs;
+
+/// Builds an org file with `sections` top-level headings, each holding a
+/// subsection and a mix of every kind of question
+fn synthetic_notes(sections: usize) -> String {
+    let mut notes = String::new();
+    for s in 0..sections {
+        notes += &format!("* Section {}\n", s);
+        for t in 0..5 {
+            notes += &format!("  - Term {} of {} :: The definition of term {}\n", t, s, t);
+        }
+        notes += &format!("** Subsection {}\n", s);
+        for l in 1..=4 {
+            notes += &format!(
+                "  {}) Step {} of the process\n     - A detail about it\n",
+                l, l
+            );
+        }
+        for b in 0..5 {
+            notes += &format!("  - Fact number {} about section {}\n", b, s);
+        }
+    }
+    notes
+}
+
+/// Times crawling with the recipe's regexes compiled once, next to building a
+/// new `Crawler` (and so compiling them again) for every parse. Changes can be
+/// compared with `cargo bench -- --save-baseline before` and then
+/// `cargo bench -- --baseline before`
+fn parse_large_notes(c: &mut Criterion) {
+    let recipe = fs::read_to_string("confs/borg.toml").unwrap();
+    let crawler = Crawler::new(&recipe).unwrap();
+    let notes = synthetic_notes(500);
+    let mut group = c.benchmark_group("parse 500 sections");
+    group.bench_function("compiled once", |b| {
+        b.iter(|| crawler.parse_sections(&notes).unwrap())
+    });
+    group.bench_function("compiled per parse", |b| {
+        b.iter(|| {
+            Crawler::new(&recipe)
+                .unwrap()
+                .parse_sections(&notes)
+                .unwrap()
+        })
+    });
+    group.finish();
+}
+
+criterion_group! {
+    name = benches;
+    config = Criterion::default().sample_size(10);
+    targets = parse_large_notes
+}
+criterion_main!(benches);
@@ -1,11 +1,24 @@
 use super::{
     data::{QCSettings, Quizcrawler},
-    util,
+    persist, util,
+};
+use crate::{
+    core::schedule::{self, SchedulerKind},
+    crawler::{
+        data::Crawler,
+        registry::{Recipes, Registry},
+    },
 };
-use crate::crawler::data::Crawler;
 use crossterm::event::{self, Event};
-use std::{error::Error, fs};
-use structopt::StructOpt;
+use std::{
+    error::Error,
+    fs,
+    io::{self, BufRead},
+};
+use structopt::{
+    clap::{self, ErrorKind},
+    StructOpt,
+};
 
 #[derive(StructOpt)]
 #[structopt(
@@ -13,19 +26,133 @@ use structopt::StructOpt;
     about = "Automagically generate interactive quizzes from preexisting notes"
 )]
 struct QCArgs {
-    /// The file containing the notes to be scraped during quiz generation
-    notes: String,
-    /// The TOML file containing the grammar used to parse the note file
-    recipe: String,
+    /// The file containing the notes to be scraped during quiz generation, or
+    /// a directory to search for files ending in one of the recipe's `exts`
+    notes: Option<String>,
+    /// The TOML file containing the grammar used to parse the note file. If
+    /// left out, one is picked by the extension of each file of notes
+    recipe: Option<String>,
+    /// The name of a built in recipe, or of one in the recipe directory, to
+    /// use for every file of notes
+    #[structopt(short, long = "use", name = "NAME")]
+    use_recipe: Option<String>,
+    /// The spaced repetition algorithm to use (leitner, sm2 or fsrs), which is
+    /// remembered for next time
+    #[structopt(short, long)]
+    scheduler: Option<SchedulerKind>,
+    /// The chance of recall that the fsrs scheduler aims for, between 0 and 1.
+    /// Only given when fsrs is the scheduler in use, saved or passed in
+    #[structopt(short, long, parse(try_from_str = schedule::parse_retention))]
+    retention: Option<f64>,
+    #[structopt(subcommand)]
+    command: Option<Command>,
+}
+
+#[derive(StructOpt)]
+enum Command {
+    /// Checks that a recipe's regexes compile and, given some notes, shows
+    /// which rule picked out each line and which lines no rule matched
+    CheckRecipe {
+        /// The TOML file containing the grammar to check
+        recipe: String,
+        /// A file of notes to try the recipe on
+        notes: Option<String>,
+    },
 }
 
 pub fn run() -> Result<(), Box<dyn Error>> {
     let args = QCArgs::from_args();
-    let crawler_recipe = fs::read_to_string(&args.recipe)?;
-    let crawler = Crawler::new(&crawler_recipe)?;
-    let tree = crawler.parse_file(&args.notes);
+    let notes = match (args.command, args.notes) {
+        (Some(Command::CheckRecipe { recipe, notes }), _) => {
+            return check_recipe(&recipe, notes.as_deref())
+        }
+        (None, Some(notes)) => notes,
+        _ => clap::Error::with_description(
+            "The notes are needed to start a quiz",
+            ErrorKind::MissingRequiredArgument,
+        )
+        .exit(),
+    };
+    // The registry is only read when it's needed, so a broken recipe in it
+    // can't get in the way of one given explicitly
+    let (explicit, registry);
+    let recipes: &dyn Recipes = match (args.recipe, args.use_recipe) {
+        (Some(recipe), _) => {
+            explicit = Crawler::new(&fs::read_to_string(&recipe)?)?;
+            &explicit
+        }
+        (None, Some(name)) => {
+            registry = Registry::load(Some(&persist::get_recipe_dir()))?;
+            registry.get(&name).ok_or_else(|| {
+                format!(
+                    "there's no recipe called {}, the recipes are: {}",
+                    name,
+                    registry.names().join(", ")
+                )
+            })?
+        }
+        (None, None) => {
+            registry = Registry::load(Some(&persist::get_recipe_dir()))?;
+            &registry
+        }
+    };
+    let mut tree = recipes.parse_path(&notes)?;
+
+    let mut settings = QCSettings::default();
+    // Anything worth reporting is kept until the TUI has let go of the terminal
+    let mut notices = Vec::new();
+    let set_aside = || match persist::set_aside_state() {
+        Ok(path) => format!("it was kept as {}", path.display()),
+        Err(_) => "it will be overwritten".to_owned(),
+    };
+    let saved = persist::load_state().unwrap_or_else(|e| {
+        notices.push(format!(
+            "Couldn't read the saved progress ({}), so the quiz started fresh and {}",
+            e,
+            set_aside()
+        ));
+        None
+    });
+    // Only progress saved for these same notes is carried over
+    let saved = saved.filter(|s| {
+        let same = s.tree.name == tree.name;
+        if !same {
+            notices.push(format!(
+                "The saved progress is for {}, not {}, so {}",
+                s.tree.name,
+                tree.name,
+                set_aside()
+            ));
+        }
+        same
+    });
+    if let Some(saved) = saved {
+        let missing = tree.merge_progress(&saved.tree);
+        if !missing.is_empty() {
+            notices.push(format!(
+                "{} saved question(s) no longer appear in {}:",
+                missing.len(),
+                notes
+            ));
+            notices.extend(missing.iter().map(|q| format!("  - {:?}", q.data)));
+        }
+        settings = saved.settings;
+    }
+    if let Some(scheduler) = args.scheduler {
+        settings.scheduler = scheduler;
+    }
+    // The saved settings might already use fsrs, so this waits until they're in
+    match (&mut settings.scheduler, args.retention) {
+        (SchedulerKind::Fsrs { retention }, Some(new)) => *retention = new,
+        (_, Some(_)) => clap::Error::with_description(
+            "--retention only applies to the fsrs scheduler, which isn't in use, so --scheduler fsrs is needed too",
+            ErrorKind::MissingRequiredArgument,
+        )
+        .exit(),
+        _ => {}
+    }
 
-    let mut quizcrawler = Quizcrawler::new(QCSettings::default(), tree);
+    let mut quizcrawler = Quizcrawler::new(settings, tree);
 
     let mut tui = util::setup_tui()?;
 
@@ -34,13 +161,82 @@ pub fn run() -> Result<(), Box<dyn Error>> {
         if let Event::Key(key) = event::read()? {
             quizcrawler.handle_key(key);
         }
+        if let Some(location) = quizcrawler.take_edit() {
+            // The editor needs the terminal to itself until it's closed
+            util::teardown_tui(tui)?;
+            let file = location.file.unwrap_or_default();
+            // All of the notes are crawled again so the ids come out the same
+            let fresh = util::open_editor(&file, location.start.0)
+                .and_then(|_| Ok(recipes.parse_path(&notes)?));
+            match fresh {
+                Ok(fresh) => quizcrawler.refresh(&fresh),
+                // A mistake in the notes shouldn't cost the progress made so far
+                Err(e) => {
+                    eprintln!("Couldn't refresh the notes: {}", e);
+                    eprintln!("Press ENTER to go back to the quiz...");
+                    io::stdin().lock().read_line(&mut String::new())?;
+                }
+            }
+            tui = util::setup_tui()?;
+        }
     }
 
     util::teardown_tui(tui)?;
 
-    // FIXME: I shouldn't be here...
-    use super::persist::save_state;
-    save_state(&quizcrawler);
+    persist::save_state(&quizcrawler);
+
+    for notice in notices {
+        eprintln!("{}", notice);
+    }
+
+    Ok(())
+}
 
+/// Prints every regex error in a recipe, then how it covers the notes
+fn check_recipe(recipe: &str, notes: Option<&str>) -> Result<(), Box<dyn Error>> {
+    // Skip `Crawler::new` so that every bad regex is reported, not just the first
+    let crawler: Crawler = toml::from_str(&fs::read_to_string(recipe)?)?;
+    let errors = crawler.check_regexes();
+    if !crawler.description.is_empty() {
+        println!("{}: {}", recipe, crawler.description);
+    }
+    if errors.is_empty() {
+        println!("Every regex in {} compiles", recipe);
+    }
+    for error in &errors {
+        println!("{}", error);
+    }
+    let notes = match notes {
+        Some(notes) if errors.is_empty() => notes,
+        _ => return Ok(()),
+    };
+    let src = fs::read_to_string(notes)?;
+    // Label every byte of the notes with the rule that took it
+    let mut rules = vec![None; src.len()];
+    for coverage in crawler.coverage(&src)? {
+        for rule in &mut rules[coverage.range] {
+            *rule = Some(coverage.rule);
+        }
+    }
+    let mut offset = 0;
+    let mut unmatched = 0;
+    for (number, line) in src.split('\n').enumerate() {
+        let mut labels: Vec<&str> = Vec::new();
+        for (i, _) in line.char_indices().filter(|(_, c)| !c.is_whitespace()) {
+            let label = rules[offset + i].unwrap_or("UNMATCHED");
+            if !labels.contains(&label) {
+                labels.push(label);
+            }
+        }
+        if labels.contains(&"UNMATCHED") {
+            unmatched += 1;
+        }
+        println!("{:>5} {:<16} | {}", number + 1, labels.join("+"), line);
+        offset += line.len() + 1;
+    }
+    println!(
+        "\n{} line(s) of {} had text that no rule matched",
+        unmatched, notes
+    );
     Ok(())
 }
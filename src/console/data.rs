@@ -1,15 +1,22 @@
 use crate::core::{
-    data::Section,
-    quiz::{DSettings, Dispatcher, MultipleChoice, QuizProgress, QuizRef},
+    data::{Location, QuestionRef, Section},
+    quiz::{
+        BulletRecall, Cloze, CodeRecall, DSettings, Dispatcher, ListStep, MultipleChoice,
+        QuizProgress, QuizRef, StepOrder, TypedAnswer,
+    },
+    schedule::SchedulerKind,
 };
 use crossterm::event::{KeyCode, KeyEvent, KeyModifiers};
 use serde::{Deserialize, Serialize};
-use std::collections::HashMap;
+use std::{collections::HashMap, rc::Rc};
 
 // Trim back things that don't need to be public
 
 #[derive(Clone, Copy, Default, Serialize, Deserialize)]
-pub struct QCSettings {}
+pub struct QCSettings {
+    #[serde(default)]
+    pub scheduler: SchedulerKind,
+}
 
 #[derive(Serialize, Deserialize)]
 pub struct Quizcrawler {
@@ -17,6 +24,9 @@ pub struct Quizcrawler {
     pub settings: QCSettings,
     #[serde(skip)]
     pub state_stack: Vec<State>, // FIXME: Make this a newtype and implement Default
+    /// Where the notes should be opened for editing, once the TUI is suspended
+    #[serde(skip)]
+    edit: Option<Location>,
 }
 
 #[derive(Default)]
@@ -35,14 +45,62 @@ impl TreeState {
     }
 }
 
+/// A single line of text being typed, with a cursor (as a byte offset)
+#[derive(Clone, Default)]
+pub struct TextInput {
+    pub text: String,
+    pub cursor: usize,
+}
+
+impl TextInput {
+    pub fn insert(&mut self, c: char) {
+        self.text.insert(self.cursor, c);
+        self.cursor += c.len_utf8();
+    }
+
+    pub fn backspace(&mut self) {
+        if self.left() {
+            self.text.remove(self.cursor);
+        }
+    }
+
+    pub fn delete(&mut self) {
+        if self.cursor < self.text.len() {
+            self.text.remove(self.cursor);
+        }
+    }
+
+    /// Moves the cursor back a character, returning false if it couldn't
+    pub fn left(&mut self) -> bool {
+        match self.text[..self.cursor].chars().next_back() {
+            Some(c) => {
+                self.cursor -= c.len_utf8();
+                true
+            }
+            None => false,
+        }
+    }
+
+    pub fn right(&mut self) {
+        if let Some(c) = self.text[self.cursor..].chars().next() {
+            self.cursor += c.len_utf8();
+        }
+    }
+}
+
 #[derive(Clone)]
 pub struct QuestionState {
     pub quiz: QuizRef,
+    pub question: QuestionRef,
     pub progress: QuizProgress,
+    pub input: TextInput,
+    /// Whether to show where in the notes the question came from
+    pub show_location: bool,
 }
 
 // FIXME: This could use some more thought
 pub enum State {
+    Home,
     TreeView(TreeState),
     Dispatch(Dispatcher),
     AskQuestion(QuestionState),
@@ -57,17 +115,83 @@ impl State {
     }
 }
 
+/// Creates a `Dispatcher` with every type of quiz registered
+fn dispatcher(settings: DSettings, tree: &Section, path: &[String]) -> Dispatcher {
+    let mut dispatcher = Dispatcher::new(settings, tree, path);
+    dispatcher.register_quiz(MultipleChoice::default());
+    dispatcher.register_quiz(TypedAnswer::default());
+    dispatcher.register_quiz(ListStep::default());
+    dispatcher.register_quiz(StepOrder::default());
+    dispatcher.register_quiz(BulletRecall::default());
+    dispatcher.register_quiz(Cloze::default());
+    dispatcher.register_quiz(CodeRecall::default());
+    dispatcher
+}
+
 impl Quizcrawler {
     pub fn new(settings: QCSettings, tree: Section) -> Self {
         Self {
             tree,
             settings,
-            state_stack: vec![State::TreeView(TreeState::default())],
+            state_stack: vec![State::Home],
+            edit: None,
         }
     }
 
+    /// Takes where the notes were asked to be opened for editing, if they were
+    pub fn take_edit(&mut self) -> Option<Location> {
+        self.edit.take()
+    }
+
+    /// Updates the tree from a fresh crawl of the notes, keeping all progress,
+    /// then sets the question on screen again so its quiz shows the changes
+    pub fn refresh(&mut self, fresh: &Section) {
+        let ids = self.tree.refresh(fresh);
+        for state in &mut self.state_stack {
+            if let State::Dispatch(dispatcher) = state {
+                dispatcher.restamp(&ids);
+            }
+        }
+        if let Some(State::AskQuestion(state)) = self.state_stack.last_mut() {
+            let mut quiz = state.quiz.borrow_mut();
+            let mut ctx = quiz.get_context().clone();
+            ctx.location = state.question.borrow().location.clone();
+            quiz.set_context(&ctx);
+            quiz.set_question(Rc::clone(&state.question));
+            state.input = TextInput::default();
+        }
+    }
+
+    /// Asks for the notes behind a question to be opened, if it's known where
+    /// they are
+    fn edit_question(&mut self, question: &QuestionRef) {
+        self.edit = question
+            .borrow()
+            .location
+            .clone()
+            .filter(|l| l.file.is_some());
+    }
+
     pub fn handle_key(&mut self, key: KeyEvent) {
         match self.state_stack.last_mut() {
+            Some(State::Home) => match key.code {
+                KeyCode::Char('q') => {
+                    self.rewind();
+                }
+                KeyCode::Char('t') => self.state_stack.push(State::TreeView(TreeState::default())),
+                // Review everything that's new or due, wherever it is in the tree
+                KeyCode::Char(' ') => {
+                    let settings = DSettings {
+                        recursive: true,
+                        due_only: true,
+                        scheduler: self.settings.scheduler,
+                        ..Default::default()
+                    };
+                    let dispatcher = dispatcher(settings, &self.tree, &[]);
+                    self.state_stack.push(State::Dispatch(dispatcher))
+                }
+                _ => {}
+            },
             Some(State::TreeView(state)) => {
                 let node = self.tree.child_at_path(&state.path).unwrap();
                 let child_names: Vec<_> = node.children.iter().map(|x| &x.name).collect();
@@ -88,13 +212,12 @@ impl Quizcrawler {
                     KeyCode::Char(' ') => {
                         let mut path = state.path.clone();
                         path.push(child_names[current].to_owned());
-                        let section = self.tree.child_at_path(&path).unwrap();
                         let settings = DSettings {
                             recursive: key.modifiers.contains(KeyModifiers::CONTROL),
+                            scheduler: self.settings.scheduler,
                             ..Default::default()
                         };
-                        let mut dispatcher = Dispatcher::new(settings, section);
-                        dispatcher.register_quiz(MultipleChoice::default());
+                        let dispatcher = dispatcher(settings, &self.tree, &path);
                         self.state_stack.push(State::Dispatch(dispatcher))
                     }
                     KeyCode::Left => {
@@ -104,21 +227,100 @@ impl Quizcrawler {
                 }
             }
             Some(State::AskQuestion(state)) => {
-                if !state.quiz.borrow().get_choices().is_empty() {
+                let result = if !state.quiz.borrow().get_choices().is_empty() {
                     match key.code {
                         KeyCode::Char('q') => {
                             self.rewind();
+                            None
                         }
-                        KeyCode::Char('h') => state.quiz.borrow_mut().get_hint(),
-                        KeyCode::Char(c) => {
-                            let result = state.quiz.borrow_mut().answer(&c.to_string());
-                            if let Some(result) = result {
-                                let state = state.clone();
-                                self.state_stack.pop();
-                                self.state_stack.push(State::AnswerQuestion(state, result))
+                        KeyCode::Char('h') => {
+                            state.quiz.borrow_mut().get_hint();
+                            None
+                        }
+                        KeyCode::Char('l') => {
+                            state.show_location = !state.show_location;
+                            None
+                        }
+                        KeyCode::Char('e') => {
+                            let question = Rc::clone(&state.question);
+                            self.edit_question(&question);
+                            None
+                        }
+                        KeyCode::Char(c) => state.quiz.borrow_mut().answer(&c.to_string()),
+                        _ => None,
+                    }
+                } else {
+                    // With no choices to pick from, the answer is typed out
+                    let input = &mut state.input;
+                    match key.code {
+                        KeyCode::Esc => {
+                            self.rewind();
+                            None
+                        }
+                        KeyCode::Tab => {
+                            state.quiz.borrow_mut().get_hint();
+                            None
+                        }
+                        KeyCode::Enter => {
+                            let mut quiz = state.quiz.borrow_mut();
+                            let result = quiz.answer(&input.text);
+                            // Start the next line once this one has been taken
+                            if quiz.is_multiline() && result.is_none() {
+                                *input = TextInput::default();
                             }
+                            result
+                        }
+                        KeyCode::Char('l') if key.modifiers.contains(KeyModifiers::CONTROL) => {
+                            state.show_location = !state.show_location;
+                            None
+                        }
+                        KeyCode::Char('e') if key.modifiers.contains(KeyModifiers::CONTROL) => {
+                            let question = Rc::clone(&state.question);
+                            self.edit_question(&question);
+                            None
+                        }
+                        KeyCode::Char(c) => {
+                            input.insert(c);
+                            None
+                        }
+                        KeyCode::Backspace => {
+                            input.backspace();
+                            None
+                        }
+                        KeyCode::Delete => {
+                            input.delete();
+                            None
                         }
-                        _ => {}
+                        KeyCode::Left => {
+                            input.left();
+                            None
+                        }
+                        KeyCode::Right => {
+                            input.right();
+                            None
+                        }
+                        KeyCode::Up | KeyCode::Down => {
+                            let offset = if key.code == KeyCode::Up { -1 } else { 1 };
+                            let carry = key
+                                .modifiers
+                                .intersects(KeyModifiers::SHIFT | KeyModifiers::CONTROL);
+                            state.quiz.borrow_mut().select(offset, carry);
+                            None
+                        }
+                        KeyCode::Home => {
+                            input.cursor = 0;
+                            None
+                        }
+                        KeyCode::End => {
+                            input.cursor = input.text.len();
+                            None
+                        }
+                        _ => None,
+                    }
+                };
+                if let Some(result) = result {
+                    if let Some(State::AskQuestion(state)) = self.state_stack.pop() {
+                        self.state_stack.push(State::AnswerQuestion(state, result))
                     }
                 }
             }
@@ -130,6 +332,13 @@ impl Quizcrawler {
                     state.quiz.borrow_mut().i_was_right();
                     *correct = true;
                 }
+                KeyCode::Char('l') => {
+                    state.show_location = !state.show_location;
+                }
+                KeyCode::Char('e') => {
+                    let question = Rc::clone(&state.question);
+                    self.edit_question(&question);
+                }
                 KeyCode::Char(' ') => {
                     self.state_stack.pop();
                 }
@@ -142,9 +351,15 @@ impl Quizcrawler {
     pub fn tick(&mut self) -> bool {
         match self.state_stack.last_mut() {
             Some(State::Dispatch(dispatcher)) => {
-                if let Some(quiz) = dispatcher.next() {
+                if let Some((quiz, question)) = dispatcher.next().zip(dispatcher.current()) {
                     let progress = dispatcher.progress();
-                    let state = QuestionState { quiz, progress };
+                    let state = QuestionState {
+                        quiz,
+                        question,
+                        progress,
+                        input: TextInput::default(),
+                        show_location: false,
+                    };
                     self.state_stack.push(State::AskQuestion(state));
                 } else {
                     self.rewind();
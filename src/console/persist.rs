@@ -1,18 +1,51 @@
 use super::data::Quizcrawler;
 use directories::ProjectDirs;
 use ron::ser::{to_string_pretty, PrettyConfig};
-use std::fs;
+use std::{
+    error::Error,
+    fs,
+    io::{self, ErrorKind},
+    path::PathBuf,
+};
 
 fn get_project_dir() -> ProjectDirs {
     ProjectDirs::from("", "", "Quizcrawler")
         .expect("No valid home directory could be found for this user!")
 }
 
+fn get_state_path() -> PathBuf {
+    get_project_dir().config_dir().join("saved_tree.ron")
+}
+
+/// Where recipes are looked for, on top of the ones built in
+pub fn get_recipe_dir() -> PathBuf {
+    get_project_dir().config_dir().join("recipes")
+}
+
 // FIXME: This needs error handling, not two unwraps...
 pub fn save_state(state: &Quizcrawler) {
-    let mut path = get_project_dir().config_dir().to_path_buf();
-    fs::create_dir_all(&path).unwrap();
-    path.push("saved_tree.ron");
+    let path = get_state_path();
+    fs::create_dir_all(path.parent().unwrap()).unwrap();
     let ron = to_string_pretty(state, PrettyConfig::new()).unwrap();
     fs::write(path, &ron).unwrap();
 }
+
+/// Moves a saved state that couldn't be read (or that's for other notes) out
+/// of the way, so that it isn't overwritten on the way out. Returns where it
+/// was moved to.
+pub fn set_aside_state() -> io::Result<PathBuf> {
+    let path = get_state_path();
+    let aside = path.with_extension("ron.bak");
+    fs::rename(&path, &aside)?;
+    Ok(aside)
+}
+
+/// Reads back the state written by `save_state`, returning `None` if nothing
+/// has been saved yet
+pub fn load_state() -> Result<Option<Quizcrawler>, Box<dyn Error>> {
+    match fs::read_to_string(get_state_path()) {
+        Ok(ron) => Ok(Some(ron::from_str(&ron)?)),
+        Err(e) if e.kind() == ErrorKind::NotFound => Ok(None),
+        Err(e) => Err(e.into()),
+    }
+}
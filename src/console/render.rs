@@ -1,9 +1,13 @@
 use super::{data::*, util::*};
 use crate::core::{
     data::Section,
+    logic::Diff,
     quiz::{QuizProgress, QuizRef},
+    schedule::DueCount,
 };
+use std::time::SystemTime;
 use tui::{
+    layout::{Constraint, Layout},
     style::{Color, Modifier, Style},
     symbols::line,
     text::{Span, Spans},
@@ -14,44 +18,103 @@ use tui::{
 impl Quizcrawler {
     pub fn render(&self, f: &mut Frame) {
         match self.state_stack.last() {
+            Some(State::Home) => home_view(&self.tree, f),
             Some(State::TreeView(s)) => tree_view(&self.tree, &s, f),
-            Some(State::AskQuestion(s)) => question_view(&s.quiz, &s.progress, None, f),
-            Some(State::AnswerQuestion(s, r)) => question_view(&s.quiz, &s.progress, Some(r), f),
+            Some(State::AskQuestion(s)) => question_view(s, None, f),
+            Some(State::AnswerQuestion(s, r)) => question_view(s, Some(r), f),
             _ => {}
         }
     }
 }
 
+fn home_view(section: &Section, f: &mut Frame) {
+    let size = f.size();
+    let due = section.due_sections(SystemTime::now());
+    let total: DueCount = due.iter().map(|&(_, count)| count).sum();
+    let sections: Vec<_> = due
+        .iter()
+        .map(|(path, count)| {
+            let info = due_info(count);
+            // The 3 is from both borders plus the spacer between path and info
+            let target_len = (size.width as usize).saturating_sub(grapheme_len(&info) + 3);
+            let mut path = path.clone();
+            path.insert(0, section.title().to_owned());
+            let path = compact_path(&path[..], "/", target_len);
+            ListItem::new(vec![render_titlebar(path, " ", info, size.width).into()])
+        })
+        .collect();
+    let title = render_titlebar(
+        "Due for review".to_string(),
+        line::HORIZONTAL,
+        due_info(&total),
+        size.width,
+    );
+    let hint = Paragraph::new(Span::raw("SPACE to review, 't' to explore the tree..."));
+    let list = List::new(sections).block(titled_block(&title));
+    let chunks = Layout::default()
+        .constraints([Constraint::Min(0), Constraint::Length(1)])
+        .split(size);
+    f.render_widget(list, chunks[0]);
+    f.render_widget(hint, chunks[1]);
+}
+
+fn due_info(count: &DueCount) -> String {
+    format!(
+        "{} Due, {} Overdue, {} New",
+        count.due, count.overdue, count.new
+    )
+}
+
 fn tree_view(section: &Section, state: &TreeState, f: &mut Frame) {
     let size = f.size();
     let node = section.child_at_path(&state.path).unwrap();
     let child_names: Vec<_> = node
         .children
         .iter()
-        .map(|x| ListItem::new(vec![compact_title(&x.name, size.width as usize - 3).into()]))
+        .map(|x| {
+            ListItem::new(vec![
+                compact_title(x.title(), size.width as usize - 3).into()
+            ])
+        })
         .collect();
     let selected_node = &node.children[state.get_selected()]; // FIXME: This panics
     let mut list_state = ListState::default();
     list_state.select(Some(state.get_selected()));
-    let title = tree_titlebar(&section.name, &state.path, selected_node, size.width);
+    let title = tree_titlebar(section.title(), &state.path, selected_node, size.width);
     let list = List::new(child_names)
         .block(titled_block(&title))
         .highlight_symbol(">");
     f.render_stateful_widget(list, size, &mut list_state);
 }
 
-fn question_view(
-    quiz: &QuizRef,
-    progress: &QuizProgress,
-    result: Option<&(bool, String)>,
-    f: &mut Frame,
-) {
+fn question_view(state: &QuestionState, result: Option<&(bool, String)>, f: &mut Frame) {
     let size = f.size();
-    let title = progress_titlebar(progress, size.width);
-    let mut text = print_context(&quiz);
+    let quiz = &state.quiz;
+    let title = progress_titlebar(&state.progress, size.width);
+    let mut text = print_context(&quiz, state.show_location);
     text.extend(print_question(&quiz));
+    let typed = quiz.borrow().get_choices().is_empty();
+    let multiline = quiz.borrow().is_multiline();
+    // Only questions crawled from a file can be opened in an editor
+    let editable = quiz
+        .borrow()
+        .get_context()
+        .location
+        .as_ref()
+        .and_then(|l| l.file.as_ref())
+        .is_some();
     if let Some(&(correct, ref answer)) = result {
-        text.extend(print_answer(correct, answer));
+        if typed && !multiline {
+            text.push(Span::raw(format!("You answered: {}\n", state.input.text)));
+        }
+        text.extend(print_answer(
+            correct,
+            answer,
+            quiz.borrow().get_diff(),
+            editable,
+        ));
+    } else if typed {
+        text.extend(print_input(&state.input, multiline, editable));
     } else {
         text.extend(print_choices(&quiz))
     }
@@ -61,10 +124,22 @@ fn question_view(
     f.render_widget(list, size);
 }
 
-fn print_context(quiz: &QuizRef) -> Vec<Span> {
+fn print_context(quiz: &QuizRef, show_location: bool) -> Vec<Span> {
     let style = Style::default().add_modifier(Modifier::ITALIC);
-    let path = quiz.borrow().get_context().path.join(" > ");
-    vec![Span::styled(format!("{}\n", path), style)]
+    let quiz = quiz.borrow();
+    let ctx = quiz.get_context();
+    let mut text = vec![Span::styled(format!("{}\n", ctx.path.join(" > ")), style)];
+    if show_location {
+        let location = ctx
+            .location
+            .as_ref()
+            .map_or("Unknown".to_owned(), |l| l.to_string());
+        text.push(Span::styled(
+            format!("From: {}\n", location),
+            style.fg(Color::DarkGray),
+        ));
+    }
+    text
 }
 
 fn print_question(quiz: &QuizRef) -> Vec<Span> {
@@ -81,9 +156,31 @@ fn print_choices(quiz: &QuizRef) -> Vec<Span> {
         .collect()
 }
 
-fn print_answer(correct: bool, answer: &str) -> Vec<Span> {
+fn print_input(input: &TextInput, multiline: bool, editable: bool) -> Vec<Span<'_>> {
+    let (before, after) = input.text.split_at(input.cursor);
+    let edit = if editable { ", CTRL+E to edit it" } else { "" };
+    let mut after = after.chars();
+    // Draw the cursor by highlighting the character under it
+    let cursor = after.next().map_or(" ".to_string(), |c| c.to_string());
+    vec![
+        Span::raw(format!("> {}", before)),
+        Span::styled(cursor, Style::default().add_modifier(Modifier::REVERSED)),
+        Span::raw(format!("{}\n\n", after.as_str())),
+        Span::raw(if multiline {
+            format!("ENTER after each line, ENTER on an empty line when done, TAB for a hint, CTRL+L for the source{}, ESC to quit...", edit)
+        } else {
+            format!(
+                "ENTER to answer, TAB for a hint, CTRL+L for the source{}, ESC to quit...",
+                edit
+            )
+        }),
+    ]
+}
+
+fn print_answer<'a>(correct: bool, answer: &str, diff: &[Diff], editable: bool) -> Vec<Span<'a>> {
     let answer_string = format!(", the answer is: {}", answer);
     let continue_string = "SPACE to continue";
+    let edit = if editable { ", 'e' to edit it" } else { "" };
     let correct_style = Style::default()
         .add_modifier(Modifier::BOLD)
         .fg(Color::Green);
@@ -91,14 +188,55 @@ fn print_answer(correct: bool, answer: &str) -> Vec<Span> {
     if correct {
         vec![
             Span::styled(format!("Well done{}\n", answer_string), correct_style),
-            Span::raw(format!("{}...", continue_string)),
+            Span::raw(format!(
+                "{}, 'l' for the source{}...",
+                continue_string, edit
+            )),
         ]
     } else {
-        vec![
-            Span::styled(format!("Sorry{}\n", answer_string), wrong_style),
-            Span::raw(format!("{}, 'o' for manual override...", continue_string)),
-        ]
+        let mut text = vec![Span::styled(
+            format!("Sorry{}\n", answer_string),
+            wrong_style,
+        )];
+        text.extend(print_diff(diff));
+        text.push(Span::raw(format!(
+            "{}, 'l' for the source{}, 'o' for manual override...",
+            continue_string, edit
+        )));
+        text
+    }
+}
+
+/// Lays out the answer against the right one, with what was missed underlined
+/// in green and what shouldn't be there crossed out in red
+fn print_diff<'a>(diff: &[Diff]) -> Vec<Span<'a>> {
+    if diff.is_empty() {
+        return Vec::new();
     }
+    let missing = Style::default()
+        .add_modifier(Modifier::UNDERLINED)
+        .fg(Color::Green);
+    let extra = Style::default()
+        .add_modifier(Modifier::CROSSED_OUT)
+        .fg(Color::Red);
+    let mut text = vec![Span::raw("\nYou answered:\n")];
+    for piece in diff {
+        match piece {
+            Diff::Same(t) => text.push(Span::raw(format!("{}{}", t.space, t.text))),
+            Diff::Missing(t) => {
+                text.push(Span::raw(t.space.clone()));
+                text.push(Span::styled(t.text.clone(), missing));
+            }
+            Diff::Extra(t) => {
+                // Extra tokens don't belong to the layout of the right answer
+                let space = if t.space.is_empty() { "" } else { " " };
+                text.push(Span::raw(space));
+                text.push(Span::styled(t.text.clone(), extra));
+            }
+        }
+    }
+    text.push(Span::raw("\n\n"));
+    text
 }
 
 fn tree_titlebar(root: &str, rest: &[String], selected: &Section, width: u16) -> String {
@@ -122,7 +260,10 @@ fn tree_titlebar(root: &str, rest: &[String], selected: &Section, width: u16) ->
 }
 
 fn progress_titlebar(progress: &QuizProgress, width: u16) -> String {
-    let learned = format!("Learned {} of {}", progress.learned, progress.questions);
+    let mut learned = format!("Learned {} of {}", progress.learned, progress.questions);
+    if let Some(recall) = progress.recall {
+        learned += &format!(", {:.0}% recall", recall * 100.0);
+    }
     let score = progress
         .score
         .map_or(String::new(), |s| format!("Your score is {:.2}%", s));
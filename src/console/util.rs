@@ -1,8 +1,9 @@
 use crossterm::{terminal, ExecutableCommand};
 use std::{
-    cmp,
+    cmp, env,
     error::Error,
     io::{self, Stdout},
+    process::Command,
 };
 use tui::{backend::CrosstermBackend, Terminal};
 use unicode_segmentation::UnicodeSegmentation;
@@ -93,6 +94,24 @@ pub fn teardown_tui(mut tui: TUI) -> Result<(), Box<dyn Error>> {
     Ok(())
 }
 
+/// Opens a file in `$EDITOR` (or vi) at a line, waiting for the editor to close
+pub fn open_editor(file: &str, line: usize) -> Result<(), Box<dyn Error>> {
+    let editor = env::var("EDITOR").unwrap_or_else(|_| "vi".to_owned());
+    // Editors are often set with their own flags, like "code --wait"
+    let mut words = editor.split_whitespace();
+    let program = words.next().unwrap_or("vi");
+    let status = Command::new(program)
+        .args(words)
+        .arg(format!("+{}", line))
+        .arg(file)
+        .status()?;
+    if status.success() {
+        Ok(())
+    } else {
+        Err(format!("{} exited with {}", program, status).into())
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -1,9 +1,17 @@
 #![allow(clippy::new_ret_no_self)]
-use super::logic;
-use serde::{Deserialize, Serialize};
+use super::{
+    logic,
+    schedule::{DueCount, Grade, Leitner, Memory, Review, Scheduler},
+};
+use serde::{Deserialize, Deserializer, Serialize};
 use std::{
+    borrow::Cow,
     cell::RefCell,
+    collections::{HashMap, HashSet},
+    fmt,
     hash::{Hash, Hasher},
+    mem,
+    ops::Range,
     rc::Rc,
     time::SystemTime,
 };
@@ -17,12 +25,55 @@ use uuid::Uuid;
 // always seen in the others.
 pub type QuestionRef = Rc<RefCell<Question>>;
 
+// Every question id is a UUIDv5 derived from a name within this namespace, so
+// the same question crawled twice always ends up with the same id
+const QUESTION_NAMESPACE: Uuid = Uuid::from_u128(0x8c5e_43a6_1f0b_4d27_9b6e_d2c4_71f3_a05e);
+
 // I really don't know how I feel about these public fields...
 #[derive(Clone, Serialize, Deserialize)]
 pub struct Section {
     pub name: String,
     pub questions: Vec<QuestionRef>,
     pub children: Vec<Section>,
+    #[serde(default)]
+    pub location: Option<Location>,
+    /// Details found at the top of a file of notes, if this section is one
+    #[serde(default)]
+    pub metadata: Option<Metadata>,
+}
+
+/// Details about a file of notes, like its title
+#[derive(Clone, Default, Serialize, Deserialize, PartialEq, Eq, Debug)]
+pub struct Metadata {
+    /// Only ever shown, since a file's section is named (and its questions'
+    /// ids derived) from its file name
+    #[serde(default)]
+    pub title: Option<String>,
+    pub author: Option<String>,
+    pub date: Option<String>,
+}
+
+/// Where something was crawled from: the notes file, when known, and the line
+/// and column (both counting from 1) that its match starts and ends on
+#[derive(Clone, Serialize, Deserialize, PartialEq, Eq, Debug)]
+pub struct Location {
+    pub file: Option<String>,
+    pub start: (usize, usize),
+    pub end: (usize, usize),
+}
+
+impl fmt::Display for Location {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match &self.file {
+            Some(file) => write!(f, "{}:", file)?,
+            None => write!(f, "line ")?,
+        }
+        write!(
+            f,
+            "{}:{}-{}:{}",
+            self.start.0, self.start.1, self.end.0, self.end.1
+        )
+    }
 }
 
 impl Section {
@@ -36,9 +87,34 @@ impl Section {
             name,
             children,
             questions,
+            location: None,
+            metadata: None,
         }
     }
 
+    /// Records the file that every location in the tree was crawled from
+    pub fn set_file(&mut self, file: &str) {
+        if let Some(location) = &mut self.location {
+            location.file = Some(file.to_owned());
+        }
+        for q in &self.questions {
+            if let Some(location) = &mut q.borrow_mut().location {
+                location.file = Some(file.to_owned());
+            }
+        }
+        for c in &mut self.children {
+            c.set_file(file);
+        }
+    }
+
+    /// The name to show for this section: its file's title, if it has one
+    pub fn title(&self) -> &str {
+        self.metadata
+            .as_ref()
+            .and_then(|m| m.title.as_deref())
+            .unwrap_or(&self.name)
+    }
+
     pub fn is_parent(&self) -> bool {
         !self.children.is_empty()
     }
@@ -51,6 +127,174 @@ impl Section {
         }
         Some(current)
     }
+
+    /// Lists the path (starting below this section) and `DueCount` of every
+    /// section in the tree with questions that are new or due
+    pub fn due_sections(&self, now: SystemTime) -> Vec<(Vec<String>, DueCount)> {
+        fn traverse_section(
+            due: &mut Vec<(Vec<String>, DueCount)>,
+            path: Vec<String>,
+            section: &Section,
+            now: SystemTime,
+        ) {
+            let count = DueCount::of(section.questions.iter().map(|q| q.borrow()), now);
+            if !count.is_empty() {
+                due.push((path.clone(), count));
+            }
+            for c in &section.children {
+                let mut path = path.clone();
+                path.push(c.name.clone());
+                traverse_section(due, path, c, now);
+            }
+        }
+        let mut due = Vec::new();
+        traverse_section(&mut due, Vec::new(), self, now);
+        due
+    }
+
+    /// Gives every question in the tree a stable id derived from its section
+    /// path and its `QuestionVariant::key`. Because the key leaves out the
+    /// answer, editing a definition or the details of a step keeps the id (and
+    /// so the progress) of a question. Changing the prompt itself, or moving a
+    /// question to another section, makes it a new question. Questions sharing
+    /// a key within the same section are told apart by the order they appear in.
+    pub fn assign_ids(&mut self) {
+        fn traverse_section(mut path: Vec<String>, section: &Section) {
+            path.push(section.name.clone());
+            let mut occurrences = HashMap::new();
+            for q in &section.questions {
+                let mut q = q.borrow_mut();
+                let key = q.data.key();
+                let n = occurrences.entry(key.clone()).or_insert(0);
+                *n += 1;
+                q.id = Question::derive_id(&path, &format!("{}#{}", key, n));
+            }
+            for c in &section.children {
+                traverse_section(path.clone(), c);
+            }
+        }
+        traverse_section(Vec::new(), self);
+    }
+
+    /// Carries the progress of every question that still exists over from a
+    /// previously saved tree. Questions are matched by id, falling back to
+    /// their `QuestionVariant` for trees saved before ids were stable, so new
+    /// questions are left untouched. The saved questions that could not be
+    /// found in this tree are returned so they can be reported.
+    pub fn merge_progress(&mut self, saved: &Section) -> Vec<Question> {
+        #[derive(Default)]
+        struct Bank {
+            questions: HashMap<Uuid, Question>,
+            // Identical questions are kept apart, in the order they appear in
+            variants: HashMap<QuestionVariant, Vec<Uuid>>,
+        }
+        fn collect(section: &Section, bank: &mut Bank) {
+            for q in &section.questions {
+                let q = q.borrow();
+                bank.variants.entry(q.data.clone()).or_default().push(q.id);
+                bank.questions.insert(q.id, q.clone());
+            }
+            for c in &section.children {
+                collect(c, bank);
+            }
+        }
+        fn restore(section: &Section, bank: &mut Bank) {
+            for q in &section.questions {
+                let mut q = q.borrow_mut();
+                let Bank {
+                    questions,
+                    variants,
+                } = &mut *bank;
+                let old = questions.remove(&q.id).or_else(|| {
+                    let ids = variants.get(&q.data)?;
+                    ids.iter().find_map(|id| questions.remove(id))
+                });
+                if let Some(old) = old {
+                    q.restore_progress(&old);
+                }
+            }
+            for c in &section.children {
+                restore(c, bank);
+            }
+        }
+        let mut bank = Bank::default();
+        collect(saved, &mut bank);
+        restore(self, &mut bank);
+        bank.questions.into_values().collect()
+    }
+
+    /// Updates every question in place from a freshly crawled copy of this
+    /// tree, keeping its progress. Questions are matched by id, falling back
+    /// to the line they start on for questions whose prompt was edited, and
+    /// take the id they have in the fresh tree. Because no `QuestionRef` is
+    /// replaced, quizzes already holding one see the change. Questions new to
+    /// the fresh tree are added to their sections. Returns the ids that changed
+    pub fn refresh(&mut self, fresh: &Section) -> HashMap<Uuid, Uuid> {
+        fn collect(section: &Section, questions: &mut Vec<QuestionRef>) {
+            questions.extend(section.questions.iter().cloned());
+            for c in &section.children {
+                collect(c, questions);
+            }
+        }
+        fn start(q: &Question) -> Option<(Option<String>, usize)> {
+            let location = q.location.as_ref()?;
+            Some((location.file.clone(), location.start.0))
+        }
+        // Adds the questions left unmatched, and any sections they need
+        fn graft(section: &mut Section, fresh: &Section, added: &HashSet<Uuid>) {
+            for q in &fresh.questions {
+                if added.contains(&q.borrow().id) {
+                    section
+                        .questions
+                        .push(Rc::new(RefCell::new(q.borrow().clone())));
+                }
+            }
+            for c in &fresh.children {
+                let i = match section.children.iter().position(|s| s.name == c.name) {
+                    Some(i) => i,
+                    None => {
+                        let mut child = Section::new(c.name.clone(), Vec::new(), Vec::new());
+                        child.location = c.location.clone();
+                        child.metadata = c.metadata.clone();
+                        section.children.push(child);
+                        section.children.len() - 1
+                    }
+                };
+                graft(&mut section.children[i], c, added);
+            }
+        }
+        let (mut stale, mut new) = (Vec::new(), Vec::new());
+        collect(self, &mut stale);
+        collect(fresh, &mut new);
+        let mut new: Vec<Question> = new.iter().map(|q| q.borrow().clone()).collect();
+        // Ids are matched across the whole tree before any lines are
+        stale.retain(|q| {
+            let id = q.borrow().id;
+            match new.iter().position(|f| f.id == id) {
+                Some(i) => {
+                    q.borrow_mut().refresh(&new.remove(i));
+                    false
+                }
+                None => true,
+            }
+        });
+        let mut ids = HashMap::new();
+        for q in stale {
+            let mut q = q.borrow_mut();
+            let line = start(&q);
+            let kind = mem::discriminant(&q.data);
+            if let Some(i) = new.iter().position(|f| {
+                line.is_some() && start(f) == line && mem::discriminant(&f.data) == kind
+            }) {
+                let old = q.id;
+                q.refresh(&new.remove(i));
+                ids.insert(old, q.id);
+            }
+        }
+        let added = new.iter().map(|q| q.id).collect();
+        graft(self, fresh, &added);
+        ids
+    }
 }
 
 #[derive(Clone, Serialize, Deserialize, Debug)]
@@ -65,11 +309,32 @@ pub struct Question {
     pub id: Uuid,
     pub data: QuestionVariant,
     pub comp_level: Strictness, // FIXME: Should this be moved up to the state machine?
-    pub mastery: u8,            // 0-10 (Leitner System)
     pub correct: usize,
     pub seen: usize,
     pub hints: f64,
     pub last_correct: SystemTime,
+    #[serde(default)]
+    pub memory: Memory,
+    /// When the `Scheduler` wants this question reviewed again, if ever seen
+    #[serde(default)]
+    pub due: Option<SystemTime>,
+    #[serde(default)]
+    pub history: Vec<Review>,
+    /// The 0-10 Leitner level that trees saved before there were schedulers
+    /// kept in place of a `Memory`, only ever read back
+    #[serde(default, skip_serializing, deserialize_with = "saved_level")]
+    mastery: Option<u8>,
+    /// Where in the notes this question was crawled from
+    #[serde(default)]
+    pub location: Option<Location>,
+    // The scheduling from before the last answer, used to override it
+    #[serde(skip)]
+    undo: Option<(Memory, Option<SystemTime>)>,
+}
+
+/// Reads the bare `mastery` number of older saved trees
+fn saved_level<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Option<u8>, D::Error> {
+    u8::deserialize(deserializer).map(Some)
 }
 
 /// Question Enum
@@ -78,6 +343,9 @@ pub enum QuestionVariant {
     Term(Term),
     List(List),
     Bullet(Bullet),
+    Cloze(Cloze),
+    Code(Code),
+    Table(Table),
 }
 
 /// Flash Cards
@@ -102,6 +370,59 @@ pub struct Bullet {
     body: String,
 }
 
+/// Text with spans marked to be blanked out. Each group of blanks is its own
+/// question, with the other groups left showing.
+#[derive(Clone, Serialize, Deserialize, PartialEq, Eq, Hash, Debug)]
+pub struct Cloze {
+    text: String,
+    blanks: Vec<Blank>,
+    group: u32,
+}
+
+/// A snippet from a code block, with the caption or line that introduced it
+#[derive(Clone, Serialize, Deserialize, PartialEq, Eq, Hash, Debug)]
+pub struct Code {
+    language: String,
+    prompt: Option<String>,
+    body: String,
+}
+
+/// A cell of a table of notes, asked for by the key at the start of its row
+/// and the header of its column
+#[derive(Clone, Serialize, Deserialize, Debug)]
+pub struct Table {
+    key: String,
+    header: String,
+    cell: String,
+    // The rest of the column, for quizzes that need wrong answers
+    others: Vec<String>,
+}
+
+/// A byte range of a cloze's text, and the group it is hidden with
+#[derive(Clone, Serialize, Deserialize, PartialEq, Eq, Hash, Debug)]
+pub struct Blank {
+    pub start: usize,
+    pub end: usize,
+    pub group: u32,
+}
+
+impl QuestionVariant {
+    /// The part of a question that identifies it, leaving out the answer
+    pub fn key(&self) -> String {
+        match self {
+            QuestionVariant::Term(t) => format!("term:{}", t.term),
+            QuestionVariant::List(l) => format!("list:{}", l.item),
+            QuestionVariant::Bullet(b) => format!("bullet:{}", b.body),
+            QuestionVariant::Cloze(c) => format!("cloze:{}#c{}", c.text, c.group),
+            QuestionVariant::Code(c) => match &c.prompt {
+                Some(prompt) => format!("code:{}:{}", c.language, prompt),
+                None => format!("code:{}:{}", c.language, c.body),
+            },
+            QuestionVariant::Table(t) => format!("table:{}:{}", t.key, t.header),
+        }
+    }
+}
+
 impl Term {
     pub fn new(term: String, definition: String) -> Question {
         Question::new(QuestionVariant::Term(Self {
@@ -125,6 +446,14 @@ impl List {
             details,
         }))
     }
+
+    pub fn order(&self) -> u32 {
+        self.order
+    }
+
+    pub fn details(&self) -> &[String] {
+        &self.details
+    }
 }
 
 impl Bullet {
@@ -133,30 +462,139 @@ impl Bullet {
     }
 }
 
+impl Cloze {
+    pub fn new(text: String, blanks: Vec<Blank>, group: u32) -> Question {
+        Question::new(QuestionVariant::Cloze(Self {
+            text,
+            blanks,
+            group,
+        }))
+    }
+
+    /// The byte ranges of the blanks hidden by this question
+    pub fn hidden(&self) -> impl Iterator<Item = Range<usize>> + '_ {
+        self.blanks
+            .iter()
+            .filter(move |b| b.group == self.group)
+            .map(|b| b.start..b.end)
+    }
+}
+
+impl Code {
+    pub fn new(language: String, prompt: Option<String>, body: String) -> Question {
+        Question::new(QuestionVariant::Code(Self {
+            language,
+            prompt,
+            body,
+        }))
+    }
+}
+
+impl Table {
+    /// Makes a question for every filled in cell of one of the rows (after
+    /// the headers) of a table, apart from the key it starts with
+    pub fn new(headers: &[String], rows: &[Vec<String>], row: usize) -> Vec<Question> {
+        let cells = &rows[row];
+        let key = match cells.first() {
+            Some(key) if !key.is_empty() => key,
+            _ => return Vec::new(),
+        };
+        let mut questions = Vec::new();
+        for (column, cell) in cells.iter().enumerate().skip(1) {
+            if cell.is_empty() {
+                continue;
+            }
+            let others = rows
+                .iter()
+                .enumerate()
+                .filter(|&(other, _)| other != row)
+                .filter_map(|(_, r)| r.get(column))
+                .filter(|c| !c.is_empty())
+                .cloned()
+                .collect();
+            questions.push(Question::new(QuestionVariant::Table(Self {
+                key: key.clone(),
+                header: headers.get(column).cloned().unwrap_or_default(),
+                cell: cell.clone(),
+                others,
+            })));
+        }
+        questions
+    }
+
+    /// The filled in cells of the rest of the asked column
+    pub fn others(&self) -> &[String] {
+        &self.others
+    }
+}
+
+// The rest of the column changes whenever any cell in it is edited, so it's
+// left out of what makes a cell the same question
+impl PartialEq for Table {
+    fn eq(&self, other: &Self) -> bool {
+        (&self.key, &self.header, &self.cell) == (&other.key, &other.header, &other.cell)
+    }
+}
+
+impl Eq for Table {}
+
+impl Hash for Table {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        (&self.key, &self.header, &self.cell).hash(state);
+    }
+}
+
 impl Question {
+    /// Creates a question with an id derived from its content alone. Use
+    /// `Section::assign_ids` to take the section path into account as well
     pub fn new(data: QuestionVariant) -> Question {
         Question {
-            id: Uuid::new_v4(),
+            id: Question::derive_id(&[] as &[&str], &data.key()),
             data,
             comp_level: Strictness::Trimmed,
-            mastery: 0,
             correct: 0,
             seen: 0,
             hints: 0.0,
             last_correct: SystemTime::now(),
+            memory: Memory::New,
+            due: None,
+            history: Vec::new(),
+            mastery: None,
+            location: None,
+            undo: None,
         }
     }
 
-    pub fn ask(&self) -> &str {
+    pub fn ask(&self) -> Cow<'_, str> {
         match &self.data {
             QuestionVariant::Term(t) => {
                 if t.inverted {
-                    &t.definition
+                    Cow::from(&t.definition)
                 } else {
-                    &t.term
+                    Cow::from(&t.term)
+                }
+            }
+            QuestionVariant::List(l) => Cow::from(format!("What is step {}?", l.order)),
+            QuestionVariant::Bullet(_) => Cow::from("Name a fact from this section"),
+            QuestionVariant::Cloze(c) => {
+                let mut blanked = String::new();
+                let mut last = 0;
+                for blank in c.hidden() {
+                    blanked += &c.text[last..blank.start];
+                    blanked += "[...]";
+                    last = blank.end;
                 }
+                Cow::from(blanked + &c.text[last..])
             }
-            _ => todo!(),
+            QuestionVariant::Code(c) => match (&c.prompt, c.language.is_empty()) {
+                (Some(prompt), _) => Cow::from(prompt),
+                (None, true) => Cow::from("Write out the snippet from this section"),
+                (None, false) => Cow::from(format!(
+                    "Write out the {} snippet from this section",
+                    c.language
+                )),
+            },
+            QuestionVariant::Table(t) => Cow::from(format!("{}, {}?", t.key, t.header)),
         }
     }
 
@@ -169,55 +607,101 @@ impl Question {
                     &t.definition
                 }
             }
-            _ => todo!(),
+            QuestionVariant::List(l) => &l.item,
+            QuestionVariant::Bullet(b) => &b.body,
+            QuestionVariant::Cloze(c) => &c.text,
+            QuestionVariant::Code(c) => &c.body,
+            QuestionVariant::Table(t) => &t.cell,
         }
     }
 
-    pub fn answer(&mut self, ans: &str, hints: f64) -> (bool, &str) {
+    pub fn answer(&mut self, ans: &str, hints: f64, scheduler: &dyn Scheduler) -> (bool, &str) {
         let right_ans = self.peek().to_owned();
         let correct = logic::check_answer(ans, &right_ans, &self.comp_level);
+        self.record(correct, hints, scheduler);
+        (correct, self.peek())
+    }
+
+    /// Records an answer that has already been checked, for quizzes that do
+    /// their own marking
+    pub fn record(&mut self, correct: bool, hints: f64, scheduler: &dyn Scheduler) {
         self.seen += 1;
-        if correct {
+        let grade = if correct {
             self.correct += 1;
             self.hints += hints;
             if hints == 0.0 {
-                self.increment_mastery();
                 self.last_correct = SystemTime::now();
+                Grade::Right
+            } else {
+                Grade::Hinted
             }
         } else {
-            self.decrement_mastery();
-        }
-        (correct, self.peek())
+            Grade::Wrong
+        };
+        self.review(grade, scheduler);
     }
 
-    pub fn override_correct(&mut self) {
+    pub fn override_correct(&mut self, scheduler: &dyn Scheduler) {
         self.correct += 1;
         if self.correct > self.seen {
             self.correct = self.seen;
-        } else {
-            // Give back the mastery lost from the wrong answer
-            self.increment_mastery();
-            // Give the new mastery for being correct
-            self.increment_mastery();
+        } else if let Some((memory, due)) = self.undo.take() {
+            // Reschedule as if the wrong answer had been right all along
+            self.history.pop();
+            self.memory = memory;
+            self.due = due;
+            self.last_correct = SystemTime::now();
+            self.review(Grade::Right, scheduler);
         }
     }
 
-    pub fn _get_variant(&mut self) -> &mut QuestionVariant {
-        &mut self.data
+    fn review(&mut self, grade: Grade, scheduler: &dyn Scheduler) {
+        let now = SystemTime::now();
+        let (memory, due) = scheduler.schedule(&self.memory, &self.history, grade, now);
+        self.undo = Some((self.memory, self.due));
+        self.memory = memory;
+        self.due = Some(due);
+        self.history.push(Review { time: now, grade });
     }
 
-    // FIXME: Make the range of min and max mastery configurable
-    fn increment_mastery(&mut self) {
-        if self.mastery < 10 {
-            self.mastery += 1;
-        }
+    pub fn derive_id(path: &[impl AsRef<str>], key: &str) -> Uuid {
+        // The unit separator can't sensibly appear in notes, so joined paths
+        // never collide
+        let mut name: Vec<_> = path.iter().map(AsRef::as_ref).collect();
+        name.push(key);
+        Uuid::new_v5(&QUESTION_NAMESPACE, name.join("\u{1f}").as_bytes())
     }
 
-    fn decrement_mastery(&mut self) {
-        if self.mastery > 0 {
-            self.mastery -= 1;
+    /// Copies the content and id (but not the learning progress) of another
+    /// question, so the next crawl of the notes still finds it
+    pub fn refresh(&mut self, other: &Question) {
+        self.id = other.id;
+        self.data = other.data.clone();
+        self.location = other.location.clone();
+    }
+
+    /// Copies the learning progress (but not the content) of another question
+    pub fn restore_progress(&mut self, other: &Question) {
+        self.correct = other.correct;
+        self.seen = other.seen;
+        self.hints = other.hints;
+        self.last_correct = other.last_correct;
+        self.memory = other.memory;
+        self.due = other.due;
+        self.history = other.history.clone();
+        // Older trees only had a Leitner level, due again an interval after
+        // the last right answer
+        if let (Memory::New, Some(level)) = (other.memory, other.mastery) {
+            if other.seen > 0 {
+                self.memory = Memory::Leitner { level };
+                self.due = Some(other.last_correct + Leitner::interval(level));
+            }
         }
     }
+
+    pub fn _get_variant(&mut self) -> &mut QuestionVariant {
+        &mut self.data
+    }
 }
 
 impl PartialEq for Question {
@@ -237,6 +721,7 @@ impl Hash for Question {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::core::schedule::Leitner;
 
     fn make_term(t: &str, d: &str) -> Question {
         Term::new(t.to_string(), d.to_string())
@@ -247,59 +732,72 @@ mod tests {
         let a = make_term("Bonjour", "Hello");
         let b = make_term("Bonjour", "Hello");
         assert_eq!(a, b);
-        assert_ne!(a.id, b.id);
+        assert_eq!(a.id, b.id);
     }
 
     #[test]
-    fn mastery_lower_bound() {
+    fn leitner_level_lower_bound() {
         let mut a = make_term("", "right");
         for _ in 1..100 {
-            a.answer("wrong", 0.0);
+            a.answer("wrong", 0.0, &Leitner);
         }
-        assert_eq!(a.mastery, 0);
+        assert_eq!(a.memory, Memory::Leitner { level: 0 });
     }
 
     #[test]
-    fn mastery_upper_bound() {
+    fn leitner_level_upper_bound() {
         let mut a = make_term("", "right");
         for _ in 1..100 {
-            a.answer("right", 0.0);
+            a.answer("right", 0.0, &Leitner);
         }
-        assert_eq!(a.mastery, 10);
+        assert_eq!(a.memory, Memory::Leitner { level: 10 });
     }
 
     #[test]
-    fn mastery_up_and_down() {
+    fn leitner_level_up_and_down() {
         let mut a = make_term("", "right");
-        a.answer("right", 0.0);
-        a.answer("right", 0.0);
-        a.answer("right", 0.0);
-        a.answer("wrong", 0.0);
-        a.answer("right", 0.0);
-        a.answer("wrong", 0.0);
-        assert_eq!(a.mastery, 2);
+        a.answer("right", 0.0, &Leitner);
+        a.answer("right", 0.0, &Leitner);
+        a.answer("right", 0.0, &Leitner);
+        a.answer("wrong", 0.0, &Leitner);
+        a.answer("right", 0.0, &Leitner);
+        a.answer("wrong", 0.0, &Leitner);
+        assert_eq!(a.memory, Memory::Leitner { level: 2 });
     }
 
     #[test]
-    fn mastery_unaffected_with_hints() {
+    fn leitner_level_unaffected_with_hints() {
         let mut a = make_term("", "right");
-        a.answer("right", 0.25);
-        a.answer("right", 0.5);
-        a.answer("right", 0.0);
-        a.answer("wrong", 0.0);
-        a.answer("right", 0.0);
-        a.answer("wrong", 0.0);
-        assert_eq!(a.mastery, 0);
+        a.answer("right", 0.25, &Leitner);
+        a.answer("right", 0.5, &Leitner);
+        a.answer("right", 0.0, &Leitner);
+        a.answer("wrong", 0.0, &Leitner);
+        a.answer("right", 0.0, &Leitner);
+        a.answer("wrong", 0.0, &Leitner);
+        assert_eq!(a.memory, Memory::Leitner { level: 0 });
+    }
+
+    #[test]
+    fn old_mastery_becomes_a_leitner_level() {
+        let mut old = make_term("q", "a");
+        old.seen = 4;
+        let ron = ron::to_string(&old).unwrap();
+        assert!(!ron.contains("mastery"));
+        let saved: Question = ron::from_str(&ron.replace("memory:New", "mastery:3")).unwrap();
+        let mut fresh = make_term("q", "a");
+        fresh.restore_progress(&saved);
+        assert_eq!(fresh.memory, Memory::Leitner { level: 3 });
+        assert_eq!(fresh.due, Some(saved.last_correct + Leitner::interval(3)));
     }
 
     #[test]
     fn last_correct_time() {
         let mut a = make_term("", "right");
         let t1 = a.last_correct.clone();
-        a.answer("wrong", 0.0);
-        a.answer("wrong", 0.0);
+        a.answer("wrong", 0.0, &Leitner);
+        a.answer("wrong", 0.0, &Leitner);
         let t2 = a.last_correct.clone();
-        a.answer("right", 0.0);
+        a.answer("right", 0.0, &Leitner);
         let t3 = a.last_correct.clone();
         assert_eq!(t1, t2);
         assert!(t3 > t2);
@@ -309,10 +807,10 @@ mod tests {
     fn last_correct_time_unchanged_with_hints() {
         let mut a = make_term("", "right");
         let t1 = a.last_correct.clone();
-        a.answer("wrong", 0.0);
-        a.answer("right", 0.5);
+        a.answer("wrong", 0.0, &Leitner);
+        a.answer("right", 0.5, &Leitner);
         let t2 = a.last_correct.clone();
-        a.answer("right", 0.0);
+        a.answer("right", 0.0, &Leitner);
         let t3 = a.last_correct.clone();
         assert_eq!(t1, t2);
         assert!(t3 > t2);
@@ -321,24 +819,24 @@ mod tests {
     #[test]
     fn override_correct_works() {
         let mut a = make_term("", "right");
-        a.answer("right", 0.0);
-        a.answer("wrong", 0.0);
+        a.answer("right", 0.0, &Leitner);
+        a.answer("wrong", 0.0, &Leitner);
         assert!(a.correct < a.seen);
-        assert_eq!(a.mastery, 0);
-        a.override_correct();
+        assert_eq!(a.memory, Memory::Leitner { level: 0 });
+        a.override_correct(&Leitner);
         assert_eq!(a.correct, a.seen);
-        assert_eq!(a.mastery, 2);
+        assert_eq!(a.memory, Memory::Leitner { level: 2 });
     }
 
     #[test]
     fn override_correct_cant_be_cheated() {
         let mut a = make_term("", "right");
-        a.answer("right", 0.0);
+        a.answer("right", 0.0, &Leitner);
         assert_eq!(a.correct, a.seen);
-        assert_eq!(a.mastery, 1);
-        a.override_correct();
+        assert_eq!(a.memory, Memory::Leitner { level: 1 });
+        a.override_correct(&Leitner);
         assert_eq!(a.correct, a.seen);
-        assert_eq!(a.mastery, 1);
+        assert_eq!(a.memory, Memory::Leitner { level: 1 });
     }
 
     #[test]
@@ -347,10 +845,185 @@ mod tests {
         assert_eq!(a.ask(), "question");
     }
 
+    #[test]
+    fn ask_list() {
+        let a = List::new(2, "Second".to_string(), Vec::new());
+        assert_eq!(a.ask(), "What is step 2?");
+        assert_eq!(a.peek(), "Second");
+    }
+
+    #[test]
+    fn ask_table() {
+        let cells = |cells: &[&str]| cells.iter().map(|c| c.to_string()).collect::<Vec<_>>();
+        let headers = cells(&["Case", "Masculine", "Plural"]);
+        let rows = vec![
+            cells(&["Nominative", "der", "die"]),
+            cells(&["Dative", "dem", ""]),
+        ];
+        let questions: Vec<_> = (0..rows.len())
+            .flat_map(|row| Table::new(&headers, &rows, row))
+            .collect();
+        // Empty cells aren't asked
+        assert_eq!(questions.len(), 3);
+        assert_eq!(questions[1].ask(), "Nominative, Plural?");
+        assert_eq!(questions[1].peek(), "die");
+        assert_eq!(questions[2].ask(), "Dative, Masculine?");
+        match &questions[2].data {
+            QuestionVariant::Table(t) => assert_eq!(t.others(), ["der"]),
+            _ => unreachable!(),
+        }
+        // Editing one cell leaves the others the same questions
+        let mut edited = rows.clone();
+        edited[0][1] = "das".to_string();
+        assert_eq!(Table::new(&headers, &edited, 1), questions[2..]);
+    }
+
     #[test]
     fn peek_term() {
         let a = make_term("question", "right");
         assert_eq!(a.peek(), "right");
         assert_eq!(a.seen, 0);
     }
+
+    #[test]
+    fn merge_progress_carries_over() {
+        let mut old = make_term("question", "right");
+        old.answer("right", 0.0, &Leitner);
+        old.answer("wrong", 0.0, &Leitner);
+        let saved = Section::new("Root".to_string(), Vec::new(), vec![old]);
+        let mut fresh = Section::new(
+            "Root".to_string(),
+            Vec::new(),
+            vec![make_term("question", "right"), make_term("new", "fresh")],
+        );
+        let missing = fresh.merge_progress(&saved);
+        assert!(missing.is_empty());
+        let merged = fresh.questions[0].borrow();
+        assert_eq!((merged.correct, merged.seen), (1, 2));
+        assert_eq!(fresh.questions[1].borrow().seen, 0);
+    }
+
+    #[test]
+    fn merge_progress_reports_missing() {
+        let child = Section::new(
+            "Child".to_string(),
+            Vec::new(),
+            vec![make_term("gone", "forever")],
+        );
+        let saved = Section::new("Root".to_string(), vec![child], Vec::new());
+        let mut fresh = Section::new("Root".to_string(), Vec::new(), Vec::new());
+        let missing = fresh.merge_progress(&saved);
+        assert_eq!(missing, vec![make_term("gone", "forever")]);
+    }
+
+    #[test]
+    fn merge_progress_keeps_duplicates_apart() {
+        let mut first = make_term("question", "right");
+        first.answer("right", 0.0, &Leitner);
+        let mut second = make_term("question", "right");
+        second.answer("wrong", 0.0, &Leitner);
+        // Saved before ids were stable, so only the variants can match them
+        first.id = Uuid::from_u128(1);
+        second.id = Uuid::from_u128(2);
+        let saved = Section::new("Root".to_string(), Vec::new(), vec![first, second]);
+        let mut fresh = Section::new(
+            "Root".to_string(),
+            Vec::new(),
+            vec![
+                make_term("question", "right"),
+                make_term("question", "right"),
+            ],
+        );
+        fresh.assign_ids();
+        assert!(fresh.merge_progress(&saved).is_empty());
+        let correct: Vec<_> = fresh.questions.iter().map(|q| q.borrow().correct).collect();
+        assert_eq!(correct, [1, 0]);
+    }
+
+    #[test]
+    fn ids_ignore_the_answer() {
+        let mut old = Section::new("Root".to_string(), Vec::new(), vec![make_term("q", "a")]);
+        let mut new = Section::new("Root".to_string(), Vec::new(), vec![make_term("q", "b")]);
+        old.assign_ids();
+        new.assign_ids();
+        assert_eq!(old.questions[0].borrow().id, new.questions[0].borrow().id);
+    }
+
+    #[test]
+    fn ids_depend_on_path() {
+        let mut a = Section::new("A".to_string(), Vec::new(), vec![make_term("q", "a")]);
+        let mut b = Section::new("B".to_string(), Vec::new(), vec![make_term("q", "a")]);
+        a.assign_ids();
+        b.assign_ids();
+        assert_ne!(a.questions[0].borrow().id, b.questions[0].borrow().id);
+    }
+
+    #[test]
+    fn ids_distinguish_duplicates() {
+        let questions = vec![make_term("q", "a"), make_term("q", "b")];
+        let mut a = Section::new("A".to_string(), Vec::new(), questions);
+        a.assign_ids();
+        assert_ne!(a.questions[0].borrow().id, a.questions[1].borrow().id);
+    }
+
+    #[test]
+    fn merge_progress_survives_edits() {
+        let mut old = make_term("question", "right");
+        old.answer("right", 0.0, &Leitner);
+        let mut saved = Section::new("Root".to_string(), Vec::new(), vec![old]);
+        let mut fresh = Section::new(
+            "Root".to_string(),
+            Vec::new(),
+            vec![make_term("question", "more right")],
+        );
+        saved.assign_ids();
+        fresh.assign_ids();
+        assert!(fresh.merge_progress(&saved).is_empty());
+        assert_eq!(fresh.questions[0].borrow().correct, 1);
+    }
+
+    #[test]
+    fn refresh_keeps_progress() {
+        let at_line = |line, mut q: Question| {
+            q.location = Some(Location {
+                file: Some("notes.org".to_string()),
+                start: (line, 1),
+                end: (line, 20),
+            });
+            q
+        };
+        let mut old = at_line(1, make_term("question", "rihgt"));
+        old.answer("rihgt", 0.0, &Leitner);
+        let questions = vec![old, at_line(2, make_term("typo", "answer"))];
+        let mut tree = Section::new("Root".to_string(), Vec::new(), questions);
+        let questions = vec![
+            at_line(1, make_term("question", "right")),
+            at_line(2, make_term("fixed", "answer")),
+            at_line(3, make_term("added", "answer")),
+        ];
+        let child = Section::new(
+            "New".to_string(),
+            Vec::new(),
+            vec![at_line(5, make_term("nested", "answer"))],
+        );
+        let mut fresh = Section::new("Root".to_string(), vec![child], questions);
+        tree.assign_ids();
+        fresh.assign_ids();
+        let old_ids: Vec<_> = tree.questions.iter().map(|q| q.borrow().id).collect();
+        let fresh_ids: Vec<_> = fresh.questions.iter().map(|q| q.borrow().id).collect();
+        let changed = tree.refresh(&fresh);
+        let first = tree.questions[0].borrow();
+        assert_eq!((first.peek(), first.correct), ("right", 1));
+        // An edited prompt is still found by the line it's on, but takes the
+        // fresh id so the next crawl finds it too
+        assert_eq!(tree.questions[1].borrow().ask(), "fixed");
+        let ids: Vec<_> = tree.questions.iter().map(|q| q.borrow().id).collect();
+        assert_eq!(ids, fresh_ids);
+        assert_eq!(changed.len(), 1);
+        assert_eq!(changed[&old_ids[1]], fresh_ids[1]);
+        // New questions are added, along with any new sections
+        assert_eq!(tree.questions[2].borrow().ask(), "added");
+        assert_eq!(tree.children[0].name, "New");
+        assert_eq!(tree.children[0].questions[0].borrow().ask(), "nested");
+    }
 }
@@ -1,4 +1,5 @@
 use super::data::Strictness;
+use std::collections::HashSet;
 
 /// This will eventually take many different strictness values and determine if
 /// two strings are close enough to be considered the same.
@@ -12,3 +13,281 @@ pub fn check_answer(ans: &str, correct: &str, level: &Strictness) -> bool {
         Strictness::Caseless => ans.trim().to_lowercase() == correct.trim().to_lowercase(),
     }
 }
+
+/// How similar two strings are, from 0.0 to 1.0, ignoring case, punctuation
+/// and spacing. Takes the better of the edit distance between the two and the
+/// fraction of the words in `correct` that appear in `ans`, so that answers
+/// can be either misspelled or reworded.
+pub fn similarity(ans: &str, correct: &str) -> f64 {
+    let (ans, correct) = (words(ans), words(correct));
+    if correct.is_empty() {
+        return if ans.is_empty() { 1.0 } else { 0.0 };
+    }
+    let (ans_str, correct_str) = (ans.join(" "), correct.join(" "));
+    let len = ans_str.chars().count().max(correct_str.chars().count());
+    let edits = 1.0 - edit_distance(&ans_str, &correct_str) as f64 / len as f64;
+    let found = correct.iter().filter(|w| ans.contains(w)).count();
+    edits.max(found as f64 / correct.len() as f64)
+}
+
+/// The rough kind of thing a string is, so that like can be compared to like
+#[derive(PartialEq, Eq, Debug)]
+pub enum Shape {
+    Number,
+    Date,
+    Word,
+    Phrase,
+    Sentence,
+}
+
+const MONTHS: &[&str] = &[
+    "jan", "feb", "mar", "apr", "may", "jun", "jul", "aug", "sep", "oct", "nov", "dec",
+];
+
+impl Shape {
+    pub fn of(s: &str) -> Shape {
+        let words = words(s);
+        let numeric = |w: &String| w.chars().all(|c| c.is_ascii_digit());
+        let month = |w: &String| MONTHS.iter().any(|m| w.starts_with(m));
+        let has_year = words.iter().any(|w| numeric(w) && w.len() == 4);
+        if !words.is_empty() && words.iter().all(numeric) {
+            if words.len() > 1 && s.contains(['/', '-']) {
+                Shape::Date
+            } else {
+                Shape::Number
+            }
+        } else if words.len() <= 4 && words.iter().any(month) && words.iter().any(numeric)
+            || words.len() <= 3 && has_year
+        {
+            Shape::Date
+        } else if words.len() <= 1 {
+            Shape::Word
+        } else if words.len() < 8 {
+            Shape::Phrase
+        } else {
+            Shape::Sentence
+        }
+    }
+}
+
+/// How alike two answers look, from 0.0 to 1.0, judging by their lengths, the
+/// words they share, their edit distance and their shape. Unlike `similarity`,
+/// this is for finding plausible wrong answers rather than marking right ones.
+pub fn resemblance(a: &str, b: &str) -> f64 {
+    let (len_a, len_b) = (a.chars().count(), b.chars().count());
+    if len_a.max(len_b) == 0 {
+        return 1.0;
+    }
+    let length = len_a.min(len_b) as f64 / len_a.max(len_b) as f64;
+    let (words_a, words_b): (HashSet<_>, HashSet<_>) = (
+        words(a).into_iter().collect(),
+        words(b).into_iter().collect(),
+    );
+    let union = words_a.union(&words_b).count();
+    let tokens = if union == 0 {
+        0.0
+    } else {
+        words_a.intersection(&words_b).count() as f64 / union as f64
+    };
+    let edits = 1.0 - edit_distance(a, b) as f64 / len_a.max(len_b) as f64;
+    let shape = if Shape::of(a) == Shape::of(b) {
+        1.0
+    } else {
+        0.0
+    };
+    (length + tokens + edits + shape) / 4.0
+}
+
+/// The lowercase alphanumeric words of a string
+fn words(s: &str) -> Vec<String> {
+    s.split(|c: char| !c.is_alphanumeric())
+        .filter(|w| !w.is_empty())
+        .map(str::to_lowercase)
+        .collect()
+}
+
+/// The Levenshtein distance between two strings, counted in characters
+pub fn edit_distance(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut row: Vec<usize> = (0..=b.len()).collect();
+    for (i, ca) in a.chars().enumerate() {
+        let mut diagonal = row[0];
+        row[0] = i + 1;
+        for (j, &cb) in b.iter().enumerate() {
+            let substitution = diagonal + (ca != cb) as usize;
+            diagonal = row[j + 1];
+            row[j + 1] = substitution.min(row[j] + 1).min(row[j + 1] + 1);
+        }
+    }
+    row[b.len()]
+}
+
+/// A piece of code that answers are compared by, along with the whitespace
+/// before it so that it can be laid out again
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct Token {
+    pub space: String,
+    pub text: String,
+}
+
+/// Splits code into words, numbers and single punctuation marks. Whitespace
+/// only separates tokens, so any spacing or indentation compares the same.
+pub fn tokenize(code: &str) -> Vec<Token> {
+    let is_word = |c: char| c.is_alphanumeric() || c == '_';
+    let mut tokens = Vec::new();
+    let mut chars = code.char_indices().peekable();
+    let mut space_start = 0;
+    while let Some((start, c)) = chars.next() {
+        if c.is_whitespace() {
+            continue;
+        }
+        let mut end = start + c.len_utf8();
+        if is_word(c) {
+            while let Some(&(i, c)) = chars.peek() {
+                if !is_word(c) {
+                    break;
+                }
+                end = i + c.len_utf8();
+                chars.next();
+            }
+        }
+        tokens.push(Token {
+            space: code[space_start..start].to_string(),
+            text: code[start..end].to_string(),
+        });
+        space_start = end;
+    }
+    tokens
+}
+
+/// A token of either the answer or the correct code, and whether it matched
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub enum Diff {
+    Same(Token),
+    Missing(Token),
+    Extra(Token),
+}
+
+/// Lines up the tokens of an answer against the correct code, keeping the
+/// longest run of tokens the two have in common
+pub fn token_diff(ans: &str, correct: &str) -> Vec<Diff> {
+    let (ans, correct) = (tokenize(ans), tokenize(correct));
+    // common[i][j] is how many tokens ans[i..] and correct[j..] share
+    let mut common = vec![vec![0; correct.len() + 1]; ans.len() + 1];
+    for i in (0..ans.len()).rev() {
+        for j in (0..correct.len()).rev() {
+            common[i][j] = if ans[i].text == correct[j].text {
+                common[i + 1][j + 1] + 1
+            } else {
+                common[i + 1][j].max(common[i][j + 1])
+            };
+        }
+    }
+    let (mut i, mut j) = (0, 0);
+    let mut diff = Vec::new();
+    while i < ans.len() || j < correct.len() {
+        if i < ans.len() && j < correct.len() && ans[i].text == correct[j].text {
+            diff.push(Diff::Same(correct[j].clone()));
+            i += 1;
+            j += 1;
+        } else if j < correct.len() && (i == ans.len() || common[i][j + 1] >= common[i + 1][j]) {
+            diff.push(Diff::Missing(correct[j].clone()));
+            j += 1;
+        } else {
+            diff.push(Diff::Extra(ans[i].clone()));
+            i += 1;
+        }
+    }
+    diff
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn edit_distances() {
+        assert_eq!(edit_distance("kitten", "sitting"), 3);
+        assert_eq!(edit_distance("", "abc"), 3);
+        assert_eq!(edit_distance("same", "same"), 0);
+    }
+
+    #[test]
+    fn similarity_forgives_typos_and_rewording() {
+        assert_eq!(similarity("Hello, World!", "hello world"), 1.0);
+        assert!(similarity("mitocondria", "Mitochondria") > 0.9);
+        assert!(
+            similarity(
+                "powerhouse is mitochondria the",
+                "The mitochondria is the powerhouse"
+            ) > 0.9
+        );
+        assert!(similarity("ribosomes", "The mitochondria is the powerhouse") < 0.5);
+    }
+
+    #[test]
+    fn shapes() {
+        assert_eq!(Shape::of("1648"), Shape::Number);
+        assert_eq!(Shape::of("3.14"), Shape::Number);
+        assert_eq!(Shape::of("12/03/1999"), Shape::Date);
+        assert_eq!(Shape::of("4 July 1776"), Shape::Date);
+        assert_eq!(Shape::of("teacher"), Shape::Word);
+        assert_eq!(Shape::of("[in the] meantime"), Shape::Phrase);
+    }
+
+    #[test]
+    fn resemblance_prefers_like_answers() {
+        let answer = "to know";
+        assert!(resemblance(answer, "to show") > resemblance(answer, "teacher"));
+        assert!(resemblance("1648", "1815") > resemblance("1648", "a long explanation of it"));
+    }
+
+    #[test]
+    fn tokens_ignore_spacing() {
+        let texts = |code| -> Vec<String> { tokenize(code).into_iter().map(|t| t.text).collect() };
+        assert_eq!(
+            texts("let x_1 = f(a,b);"),
+            ["let", "x_1", "=", "f", "(", "a", ",", "b", ")", ";"]
+        );
+        assert_eq!(
+            texts("fn main() {\n    go();\n}"),
+            texts("fn main(){ go(); }")
+        );
+        assert_eq!(tokenize("  a\n b")[1].space, "\n ");
+    }
+
+    #[test]
+    fn token_diffs() {
+        let same = |t: &str| {
+            Diff::Same(Token {
+                space: " ".to_string(),
+                text: t.to_string(),
+            })
+        };
+        let diff = token_diff("x = y + 2", "x = y * 2");
+        assert_eq!(
+            diff[..3],
+            [
+                Diff::Same(Token {
+                    space: String::new(),
+                    text: "x".to_string()
+                }),
+                same("="),
+                same("y")
+            ]
+        );
+        let changed: Vec<_> = diff[3..5]
+            .iter()
+            .map(|d| match d {
+                Diff::Missing(t) => format!("-{}", t.text),
+                Diff::Extra(t) => format!("+{}", t.text),
+                Diff::Same(t) => t.text.clone(),
+            })
+            .collect();
+        assert_eq!(changed, ["-*", "++"]);
+        assert_eq!(diff[5], same("2"));
+        assert!(token_diff("a  (b)", "a(b)")
+            .iter()
+            .all(|d| matches!(d, Diff::Same(_))));
+    }
+}
@@ -1,3 +1,4 @@
 pub mod data;
 pub mod logic;
 pub mod quiz;
+pub mod schedule;
@@ -1,11 +1,18 @@
-use super::data::{Question, QuestionRef, QuestionVariant, Section};
+use super::{
+    data::{Location, Question, QuestionRef, QuestionVariant, Section},
+    logic::{self, Diff},
+    schedule::{DueStatus, SchedulerKind},
+};
 use derive_more::{Add, Sum};
 use rand::{prelude::*, seq::IteratorRandom};
 use std::{
     cell::RefCell,
     cmp,
     collections::{HashMap, HashSet},
+    mem,
+    ops::Range,
     rc::Rc,
+    time::SystemTime,
 };
 use uuid::Uuid;
 
@@ -20,6 +27,8 @@ pub struct QuizProgress {
     pub questions: usize,
     pub learned: usize,
     pub score: Option<f64>,
+    /// The chance of recalling the current question, if the scheduler knows
+    pub recall: Option<f64>,
 }
 
 #[derive(Clone, Copy, Add, Sum)]
@@ -33,20 +42,33 @@ pub struct QuestionProgress {
 pub struct QuestionCtx {
     pub path: Vec<String>,
     pub siblings: Vec<QuestionRef>,
+    /// Questions of the same kind from anywhere in the tree, each with how
+    /// many sections up their closest common ancestor is (0 for this section)
+    pub cousins: Vec<(usize, QuestionRef)>,
+    /// Every question in the same section, in the order of the notes
+    pub section: Vec<QuestionRef>,
+    pub scheduler: SchedulerKind,
+    /// Where the question being asked was crawled from, if known
+    pub location: Option<Location>,
 }
 
 // FIXME: Ensure that all "settings" structs implement Copy
 #[derive(Clone, Copy)]
 pub struct DSettings {
     pub recursive: bool,
+    /// Only ask questions that are new or that the scheduler says are due
+    pub due_only: bool,
     pub quiz_length: usize,
+    pub scheduler: SchedulerKind,
 }
 
 impl Default for DSettings {
     fn default() -> Self {
         DSettings {
             recursive: false,
+            due_only: false,
             quiz_length: 20,
+            scheduler: SchedulerKind::default(),
         }
     }
 }
@@ -55,69 +77,115 @@ pub struct Dispatcher {
     questions: Vec<QuestionRef>,
     quizzes: Vec<QuizRef>,
     reference: HashMap<Uuid, (Question, Vec<String>)>, // FIXME: Should I really be lumping Question and Vec<String> together?
+    sections: HashMap<Vec<String>, Vec<QuestionRef>>,
     settings: DSettings,
+    current: Option<QuestionRef>,
     rng: ThreadRng,
 }
 
 // FIXME: Should this use the builder pattern?
 impl Dispatcher {
-    /// Set the list of `Question`'s to ask and `Quiz`'s to be dispatched
-    pub fn new(settings: DSettings, section: &Section) -> Self {
+    /// Set the list of `Question`'s to ask, those in the section at `path`
+    /// below the root of `tree`, and `Quiz`'s to be dispatched
+    pub fn new(settings: DSettings, tree: &Section, path: &[String]) -> Self {
         // FIXME: Add some explanations
         #[derive(Default)]
-        struct TraverseCtx {
+        struct TraverseCtx<'a> {
             questions: Vec<QuestionRef>,
             reference: HashMap<Uuid, (Question, Vec<String>)>,
+            sections: HashMap<Vec<String>, Vec<QuestionRef>>,
             settings: DSettings,
+            target: &'a [String],
         }
         fn traverse_section(ctx: &mut TraverseCtx, mut path: Vec<String>, section: &Section) {
             path.push(section.name.clone());
-            for q in &section.questions {
+            // Quizzes might need the whole tree, even the questions not asked
+            ctx.sections.insert(path.clone(), section.questions.clone());
+            // The root is at the front of the path, but not of the target
+            let below = &path[1..];
+            let asked =
+                below == ctx.target || ctx.settings.recursive && below.starts_with(ctx.target);
+            let now = SystemTime::now();
+            for q in section.questions.iter().filter(|_| asked) {
+                if ctx.settings.due_only && !DueStatus::of(&q.borrow(), now).is_due() {
+                    continue;
+                }
                 ctx.questions.push(Rc::clone(q));
                 ctx.reference.insert(
                     q.borrow().id,
                     (RefCell::clone(q).into_inner(), path.clone()),
                 );
             }
-            if ctx.settings.recursive {
-                for c in &section.children {
-                    traverse_section(ctx, path.clone(), &c);
-                }
+            for c in &section.children {
+                traverse_section(ctx, path.clone(), &c);
             }
         }
         let mut ctx = TraverseCtx {
             settings,
+            target: path,
             ..Default::default()
         };
-        traverse_section(&mut ctx, Vec::new(), section);
+        traverse_section(&mut ctx, Vec::new(), tree);
         Self {
             questions: ctx.questions,
             quizzes: Vec::new(),
             reference: ctx.reference,
+            sections: ctx.sections,
             settings,
+            current: None,
             rng: thread_rng(),
         }
     }
 
+    /// Follows questions whose ids changed when the notes were refreshed
+    pub fn restamp(&mut self, ids: &HashMap<Uuid, Uuid>) {
+        let moved: Vec<_> = ids
+            .iter()
+            .filter_map(|(old, &new)| Some((new, self.reference.remove(old)?)))
+            .collect();
+        for (id, (mut question, path)) in moved {
+            question.id = id;
+            self.reference.insert(id, (question, path));
+        }
+    }
+
     pub fn register_quiz(&mut self, quiz: impl Quiz + 'static) {
         self.quizzes.push(Rc::new(RefCell::new(Box::new(quiz))));
     }
 
+    /// The question that was last dispatched
+    pub fn current(&self) -> Option<QuestionRef> {
+        self.current.clone()
+    }
+
     /// Returns the number of questions in the set, how many have been learned,
-    /// and the current score as a percentage
+    /// the current score as a percentage and the chance of recalling the
+    /// question that was last dispatched
     pub fn progress(&self) -> QuizProgress {
         let questions = cmp::min(self.settings.quiz_length, self.questions.len());
+        let scheduler = self.settings.scheduler.scheduler();
         QuizProgress {
             questions,
             learned: questions - self.remaining_questions().len(),
             score: self.score(),
+            recall: self
+                .current
+                .as_ref()
+                .and_then(|q| scheduler.recall(&q.borrow(), SystemTime::now())),
         }
     }
 
     // FIXME: Add a configurable mastery threshold for progression
     fn remaining_questions(&self) -> Vec<QuestionRef> {
-        let mut todo: Vec<_> = self.reference.values().collect();
-        todo.sort_unstable_by_key(|(q, _)| q.mastery);
+        let scheduler = self.settings.scheduler.scheduler();
+        let now = SystemTime::now();
+        let mut todo: Vec<_> = self
+            .reference
+            .values()
+            .map(|(q, _)| (q, scheduler.urgency(q, now)))
+            .collect();
+        // The most urgent questions come first
+        todo.sort_unstable_by(|(_, a), (_, b)| b.partial_cmp(a).unwrap_or(cmp::Ordering::Equal));
         self.questions
             .iter()
             .cloned()
@@ -160,12 +228,22 @@ impl Dispatcher {
 impl Iterator for Dispatcher {
     type Item = QuizRef;
 
-    /// Sorts `Question`s by mastery, then dispatches a random `Quiz` if one
-    /// is available
+    /// Picks the `Question` most at risk of being forgotten out of those seen
+    /// the fewest times, then dispatches a random `Quiz` if one is available
     fn next(&mut self) -> Option<QuizRef> {
+        let scheduler = self.settings.scheduler.scheduler();
+        let now = SystemTime::now();
         let mut remaining = self.remaining_questions();
         remaining.shuffle(&mut self.rng);
-        remaining.sort_unstable_by_key(|q| self.question_progress(q).seen);
+        remaining.sort_by(|a, b| {
+            let seen = |q| self.question_progress(q).seen;
+            let urgency = |q: &QuestionRef| scheduler.urgency(&q.borrow(), now);
+            seen(a).cmp(&seen(b)).then(
+                urgency(b)
+                    .partial_cmp(&urgency(a))
+                    .unwrap_or(cmp::Ordering::Equal),
+            )
+        });
         let question = Rc::clone(remaining.first()?); // This was nicer as .pop()
         let quiz = self
             .quizzes
@@ -182,11 +260,30 @@ impl Iterator for Dispatcher {
             .collect();
         let (_, path) = self.reference.get(&question.borrow().id).unwrap(); // FIXME: Spooky unwrap
         let path = path.clone();
+        let section = self.sections.get(&path).cloned().unwrap_or_default();
+        let cousins = self
+            .sections
+            .iter()
+            .flat_map(|(other, questions)| {
+                let shared = path.iter().zip(other).take_while(|(a, b)| a == b).count();
+                let distance = path.len() - shared;
+                questions.iter().map(move |q| (distance, Rc::clone(q)))
+            })
+            .filter(|(_, q)| quiz.borrow().is_applicable(&q.borrow()))
+            .collect();
         {
             let mut quiz = quiz.borrow_mut();
-            quiz.set_context(&QuestionCtx { path, siblings });
-            quiz.set_question(question);
+            quiz.set_context(&QuestionCtx {
+                path,
+                siblings,
+                cousins,
+                section,
+                scheduler: self.settings.scheduler,
+                location: question.borrow().location.clone(),
+            });
+            quiz.set_question(Rc::clone(&question));
         }
+        self.current = Some(question);
         Some(quiz)
     }
 }
@@ -210,6 +307,18 @@ pub trait Quiz {
     /// Takes a user answer in the form of a `&str` and if it's valid, returns
     /// if it was correct and what the right answer was
     fn answer(&mut self, ans: &str) -> Option<(bool, String)>;
+    /// Moves the selection of quizzes that show a list to arrange, dragging the
+    /// selected item along with it if `carry` is set
+    fn select(&mut self, _offset: isize, _carry: bool) {}
+    /// Whether answers are given a line at a time, finishing with an empty one
+    fn is_multiline(&self) -> bool {
+        false
+    }
+    /// How the last answer differed from the right one, token by token, for
+    /// quizzes that mark answers that way
+    fn get_diff(&self) -> &[Diff] {
+        &[]
+    }
     /// Override the previous answer, marking it as correct
     fn i_was_right(&mut self);
     /// Checks which `QuestionVariant` is in `Question`, returning if this quiz
@@ -235,6 +344,8 @@ pub struct MultipleChoice {
     question: Option<QuestionRef>,
     context: QuestionCtx,
     choices: Vec<String>,
+    // How many choices there were before any hints
+    full_choices: usize,
     rng: ThreadRng,
 }
 
@@ -248,18 +359,42 @@ impl MultipleChoice {
 }
 
 impl Quiz for MultipleChoice {
+    /// Picks wrong answers that look like the right one, preferring those from
+    /// nearby sections of the notes and of the same kind. Table cells take
+    /// theirs from the rest of their column.
     fn set_question(&mut self, q: QuestionRef) {
         let answer = q.borrow().peek().to_string();
-        let answer_bank: HashSet<String> = self
-            .context
-            .siblings
-            .iter()
-            .map(|q| q.borrow().peek().to_string())
-            .collect();
-        self.choices = answer_bank
+        let mut answer_bank: HashMap<String, f64> = HashMap::new();
+        if let QuestionVariant::Table(t) = &q.borrow().data {
+            for cell in t.others() {
+                answer_bank.insert(cell.to_string(), logic::resemblance(&answer, cell));
+            }
+        } else {
+            let kind = mem::discriminant(&q.borrow().data);
+            let cousins = self
+                .context
+                .cousins
+                .iter()
+                .filter(|(_, c)| mem::discriminant(&c.borrow().data) == kind);
+            for (distance, cousin) in cousins {
+                let cousin = cousin.borrow().peek().to_string();
+                let score = logic::resemblance(&answer, &cousin) + 1.0 / (1.0 + *distance as f64);
+                let best = answer_bank.entry(cousin).or_insert(score);
+                *best = best.max(score);
+            }
+        }
+        answer_bank.remove(&answer);
+        // This ensures you don't get docked points if there are fewer distinct answers than settings.choices
+        self.full_choices = cmp::min(self.settings.choices, answer_bank.len() + 1);
+        let mut ranked: Vec<_> = answer_bank.into_iter().collect();
+        ranked.sort_by(|a, b| b.1.partial_cmp(&a.1).unwrap_or(cmp::Ordering::Equal));
+        // Leave some variety by drawing from the best few
+        let wrong = self.settings.choices - 1;
+        self.choices = ranked
             .into_iter()
-            .filter(|s| s != &answer)
-            .choose_multiple(&mut self.rng, self.settings.choices - 1);
+            .take(wrong * 2)
+            .map(|(s, _)| s)
+            .choose_multiple(&mut self.rng, wrong);
         self.choices.push(answer);
         self.choices.shuffle(&mut self.rng);
         self.question = Some(q);
@@ -309,10 +444,9 @@ impl Quiz for MultipleChoice {
         match self.question {
             Some(ref q) if 0 < n && n <= choices.len() => {
                 let mut q = q.borrow_mut();
-                // This ensures you don't get docked points if the section has fewer questions than settings.choices
-                let full_choices = cmp::min(self.settings.choices, self.context.siblings.len());
-                let hints = 1.0 - self.choices.len() as f64 / full_choices as f64;
-                let (correct, answer) = q.answer(&choices[n - 1], hints);
+                let hints = 1.0 - self.choices.len() as f64 / self.full_choices as f64;
+                let scheduler = self.context.scheduler.scheduler();
+                let (correct, answer) = q.answer(&choices[n - 1], hints, &*scheduler);
                 Some((correct, answer.to_string()))
             }
             _ => None,
@@ -321,14 +455,1436 @@ impl Quiz for MultipleChoice {
 
     fn i_was_right(&mut self) {
         if let Some(ref q) = self.question {
-            q.borrow_mut().override_correct()
+            q.borrow_mut()
+                .override_correct(&*self.context.scheduler.scheduler())
         }
     }
 
     fn is_applicable(&self, q: &Question) -> bool {
-        match q.data {
-            QuestionVariant::Term(_) => true,
-            _ => false,
+        matches!(q.data, QuestionVariant::Term(_) | QuestionVariant::Table(_))
+    }
+}
+
+#[derive(Default)]
+pub struct TypedAnswer {
+    question: Option<QuestionRef>,
+    context: QuestionCtx,
+    // How many characters of the answer the hints have given away
+    revealed: usize,
+}
+
+impl TypedAnswer {
+    fn hint(&self) -> Option<String> {
+        if self.revealed == 0 {
+            return None;
+        }
+        let answer = self.question.as_ref()?.borrow().peek().to_string();
+        let hint = answer
+            .chars()
+            .enumerate()
+            .map(|(i, c)| {
+                if i < self.revealed || c.is_whitespace() {
+                    c
+                } else {
+                    '_'
+                }
+            })
+            .collect();
+        Some(hint)
+    }
+}
+
+impl Quiz for TypedAnswer {
+    fn set_question(&mut self, q: QuestionRef) {
+        self.revealed = 0;
+        self.question = Some(q);
+    }
+
+    fn set_context(&mut self, ctx: &QuestionCtx) {
+        self.context = ctx.to_owned();
+    }
+
+    fn get_context(&self) -> &QuestionCtx {
+        &self.context
+    }
+
+    fn ask(&self) -> String {
+        match self.question {
+            Some(ref q) => match self.hint() {
+                Some(hint) => format!("{}\nHint: {}", q.borrow().ask(), hint),
+                None => q.borrow().ask().to_string(),
+            },
+            None => String::new(),
+        }
+    }
+
+    /// There are no choices, the answer must be typed out
+    fn get_choices(&self) -> &[String] {
+        &[]
+    }
+
+    /// Reveals one more character of the answer
+    fn get_hint(&mut self) {
+        if let Some(ref q) = self.question {
+            let len = q.borrow().peek().chars().count();
+            // Always leave at least one character to be typed
+            if self.revealed + 1 < len {
+                self.revealed += 1;
+            }
+        }
+    }
+
+    fn answer(&mut self, ans: &str) -> Option<(bool, String)> {
+        if ans.trim().is_empty() {
+            return None;
+        }
+        let mut q = self.question.as_ref()?.borrow_mut();
+        // An empty answer has nothing to reveal, but mustn't divide by zero
+        let len = q.peek().chars().count().max(1);
+        let hints = self.revealed as f64 / len as f64;
+        let scheduler = self.context.scheduler.scheduler();
+        let (correct, answer) = q.answer(ans, hints, &*scheduler);
+        Some((correct, answer.to_string()))
+    }
+
+    fn i_was_right(&mut self) {
+        if let Some(ref q) = self.question {
+            q.borrow_mut()
+                .override_correct(&*self.context.scheduler.scheduler())
+        }
+    }
+
+    fn is_applicable(&self, q: &Question) -> bool {
+        matches!(q.data, QuestionVariant::Term(_) | QuestionVariant::Table(_))
+    }
+}
+
+/// Finds the steps of the numbered list that a question belongs to, in order.
+/// A section can hold several lists, each starting when the numbering does.
+fn list_steps(question: &Question, section: &[QuestionRef]) -> Vec<QuestionRef> {
+    let mut lists: Vec<Vec<QuestionRef>> = Vec::new();
+    let mut last = 0;
+    for q in section {
+        if let QuestionVariant::List(l) = &q.borrow().data {
+            if lists.is_empty() || l.order() <= last {
+                lists.push(Vec::new());
+            }
+            last = l.order();
+            lists.last_mut().unwrap().push(Rc::clone(q));
+        }
+    }
+    let mut steps = lists
+        .into_iter()
+        .find(|l| l.iter().any(|q| q.borrow().id == question.id))
+        .unwrap_or_default();
+    steps.sort_by_key(|q| match &q.borrow().data {
+        QuestionVariant::List(l) => l.order(),
+        _ => 0,
+    });
+    steps
+}
+
+/// Asks for a step of a numbered list, either by its number or by the step
+/// before it, picking from the other steps of the same list. Hints reveal the
+/// details of the step before eliminating wrong choices.
+#[derive(Default)]
+pub struct ListStep {
+    settings: MCSettings,
+    question: Option<QuestionRef>,
+    context: QuestionCtx,
+    // When set, the question asks what comes after this step
+    previous: Option<String>,
+    choices: Vec<String>,
+    full_choices: usize,
+    details: Vec<String>,
+    revealed: usize,
+    rng: ThreadRng,
+}
+
+impl ListStep {
+    pub fn _new(settings: MCSettings) -> Self {
+        Self {
+            settings,
+            ..Self::default()
+        }
+    }
+}
+
+impl Quiz for ListStep {
+    fn set_question(&mut self, q: QuestionRef) {
+        {
+            let question = q.borrow();
+            let steps = list_steps(&question, &self.context.section);
+            let position = steps.iter().position(|s| s.borrow().id == question.id);
+            self.previous = match position {
+                Some(i) if i > 0 && self.rng.gen_bool(0.5) => {
+                    Some(steps[i - 1].borrow().peek().to_string())
+                }
+                _ => None,
+            };
+            let answer = question.peek().to_string();
+            self.choices = steps
+                .iter()
+                .map(|s| s.borrow().peek().to_string())
+                .filter(|s| s != &answer)
+                .collect::<HashSet<_>>()
+                .into_iter()
+                .choose_multiple(&mut self.rng, self.settings.choices - 1);
+            self.choices.push(answer);
+            self.choices.shuffle(&mut self.rng);
+            self.full_choices = self.choices.len();
+            self.details = match &question.data {
+                QuestionVariant::List(l) => l.details().to_vec(),
+                _ => Vec::new(),
+            };
+            self.revealed = 0;
+        }
+        self.question = Some(q);
+    }
+
+    fn set_context(&mut self, ctx: &QuestionCtx) {
+        self.context = ctx.to_owned();
+    }
+
+    fn get_context(&self) -> &QuestionCtx {
+        &self.context
+    }
+
+    fn ask(&self) -> String {
+        let mut ask = match (&self.question, &self.previous) {
+            (Some(_), Some(previous)) => format!("What comes after \"{}\"?", previous),
+            (Some(q), None) => q.borrow().ask().to_string(),
+            _ => return String::new(),
+        };
+        for detail in &self.details[..self.revealed] {
+            ask += &format!("\nHint: {}", detail);
+        }
+        ask
+    }
+
+    fn get_choices(&self) -> &[String] {
+        &self.choices[..]
+    }
+
+    fn get_hint(&mut self) {
+        if self.revealed < self.details.len() {
+            self.revealed += 1;
+        } else if let Some(ref q) = self.question {
+            if self.choices.len() > 2 {
+                let answer = q.borrow().peek().to_string();
+                let wrong = self
+                    .choices
+                    .iter()
+                    .enumerate()
+                    .filter(|&(_, s)| s != &answer)
+                    .map(|(i, _)| i)
+                    .choose(&mut self.rng)
+                    .unwrap();
+                self.choices.remove(wrong);
+            }
+        }
+    }
+
+    fn answer(&mut self, ans: &str) -> Option<(bool, String)> {
+        let n: usize = ans.parse().ok()?;
+        match self.question {
+            Some(ref q) if 0 < n && n <= self.choices.len() => {
+                let mut q = q.borrow_mut();
+                // Each detail revealed gives away a share of what the choices
+                // left to give away
+                let remaining = self.choices.len() as f64 / self.full_choices as f64;
+                let hidden = 1.0 - self.revealed as f64 / (self.details.len() + 1) as f64;
+                let hints = 1.0 - remaining * hidden;
+                let scheduler = self.context.scheduler.scheduler();
+                let (correct, answer) = q.answer(&self.choices[n - 1], hints, &*scheduler);
+                Some((correct, answer.to_string()))
+            }
+            _ => None,
+        }
+    }
+
+    fn i_was_right(&mut self) {
+        if let Some(ref q) = self.question {
+            q.borrow_mut()
+                .override_correct(&*self.context.scheduler.scheduler())
+        }
+    }
+
+    fn is_applicable(&self, q: &Question) -> bool {
+        matches!(q.data, QuestionVariant::List(_))
+    }
+}
+
+/// The fraction of pairs of items that are in the right order relative to
+/// each other (a normalised Kendall tau distance), where `ranks` holds the
+/// correct position of each item in the order they were given
+fn kendall_closeness(ranks: &[usize]) -> f64 {
+    let n = ranks.len();
+    if n < 2 {
+        return 1.0;
+    }
+    let concordant = (0..n)
+        .flat_map(|i| (i + 1..n).map(move |j| (i, j)))
+        .filter(|&(i, j)| ranks[i] < ranks[j])
+        .count();
+    concordant as f64 / (n * (n - 1) / 2) as f64
+}
+
+/// Shows every step of a numbered list out of order, asking for the order
+/// they belong in. The order can be typed as a sequence of step numbers, like
+/// "3142", or the steps can be moved into place before submitting nothing.
+/// Orders that are closer to right than wrong earn partial credit, recorded
+/// like a hint for the part that was out of order.
+#[derive(Default)]
+pub struct StepOrder {
+    question: Option<QuestionRef>,
+    context: QuestionCtx,
+    // The steps, numbered by their position here
+    shuffled: Vec<String>,
+    // The correct order, as positions in `shuffled`
+    order: Vec<usize>,
+    // The order the steps have been moved into, as positions in `shuffled`
+    arrangement: Vec<usize>,
+    selected: usize,
+    rng: ThreadRng,
+}
+
+impl StepOrder {
+    /// Reads a sequence of step numbers, separated by spaces or commas if
+    /// there are more than nine steps
+    fn parse_order(&self, ans: &str) -> Option<Vec<usize>> {
+        let numbers: Vec<&str> = if ans.contains(|c: char| c == ',' || c.is_whitespace()) {
+            ans.split(|c: char| c == ',' || c.is_whitespace())
+                .filter(|s| !s.is_empty())
+                .collect()
+        } else {
+            ans.char_indices()
+                .map(|(i, c)| &ans[i..i + c.len_utf8()])
+                .collect()
+        };
+        let order = numbers
+            .into_iter()
+            .map(|n| n.parse::<usize>().ok()?.checked_sub(1))
+            .collect::<Option<Vec<_>>>()?;
+        // Every step must appear exactly once
+        let mut sorted = order.clone();
+        sorted.sort_unstable();
+        if sorted == (0..self.shuffled.len()).collect::<Vec<_>>() {
+            Some(order)
+        } else {
+            None
+        }
+    }
+
+    fn format_order(&self, order: &[usize]) -> String {
+        let numbers: Vec<_> = order.iter().map(|i| (i + 1).to_string()).collect();
+        if self.shuffled.len() > 9 {
+            numbers.join(" ")
+        } else {
+            numbers.concat()
+        }
+    }
+}
+
+impl Quiz for StepOrder {
+    fn set_question(&mut self, q: QuestionRef) {
+        let steps: Vec<_> = list_steps(&q.borrow(), &self.context.section)
+            .iter()
+            .map(|s| s.borrow().peek().to_string())
+            .collect();
+        let mut positions: Vec<_> = (0..steps.len()).collect();
+        // Make sure the steps don't start out in order
+        while steps.len() > 1 && positions.windows(2).all(|w| w[0] < w[1]) {
+            positions.shuffle(&mut self.rng);
+        }
+        self.shuffled = positions.iter().map(|&i| steps[i].clone()).collect();
+        self.order = (0..steps.len())
+            .map(|i| positions.iter().position(|&p| p == i).unwrap())
+            .collect();
+        self.arrangement = (0..steps.len()).collect();
+        self.selected = 0;
+        self.question = Some(q);
+    }
+
+    fn set_context(&mut self, ctx: &QuestionCtx) {
+        self.context = ctx.to_owned();
+    }
+
+    fn get_context(&self) -> &QuestionCtx {
+        &self.context
+    }
+
+    fn ask(&self) -> String {
+        let steps: Vec<_> = self
+            .arrangement
+            .iter()
+            .enumerate()
+            .map(|(i, &s)| {
+                let marker = if i == self.selected { ">" } else { " " };
+                format!("{} {}) {}", marker, s + 1, self.shuffled[s])
+            })
+            .collect();
+        format!(
+            "Put these steps in order (SHIFT+UP/DOWN to move them):\n{}",
+            steps.join("\n")
+        )
+    }
+
+    /// The order is typed out or arranged, so there are no choices
+    fn get_choices(&self) -> &[String] {
+        &[]
+    }
+
+    fn get_hint(&mut self) {}
+
+    /// Takes a typed order, or submits the current arrangement if `ans` is blank
+    fn answer(&mut self, ans: &str) -> Option<(bool, String)> {
+        let order = if ans.trim().is_empty() {
+            self.arrangement.clone()
+        } else {
+            self.parse_order(ans)?
+        };
+        let ranks: Vec<_> = order
+            .iter()
+            .map(|s| self.order.iter().position(|o| o == s).unwrap())
+            .collect();
+        let closeness = kendall_closeness(&ranks);
+        // Being more right than wrong earns partial credit
+        let correct = closeness > 0.5;
+        let hints = if correct { 1.0 - closeness } else { 0.0 };
+        let scheduler = self.context.scheduler.scheduler();
+        self.question
+            .as_ref()?
+            .borrow_mut()
+            .record(correct, hints, &*scheduler);
+        let mut answer = self.format_order(&self.order);
+        if closeness < 1.0 {
+            answer += &format!(" ({:.0}% of your order was right)", closeness * 100.0);
+        }
+        Some((correct, answer))
+    }
+
+    fn select(&mut self, offset: isize, carry: bool) {
+        let last = self.arrangement.len().saturating_sub(1) as isize;
+        let target = (self.selected as isize + offset).max(0).min(last) as usize;
+        if carry {
+            let step = self.arrangement.remove(self.selected);
+            self.arrangement.insert(target, step);
+        }
+        self.selected = target;
+    }
+
+    fn i_was_right(&mut self) {
+        if let Some(ref q) = self.question {
+            q.borrow_mut()
+                .override_correct(&*self.context.scheduler.scheduler())
+        }
+    }
+
+    fn is_applicable(&self, q: &Question) -> bool {
+        matches!(q.data, QuestionVariant::List(_))
+    }
+}
+
+/// How similar a typed line must be to a bullet to count as recalling it
+const RECALL_THRESHOLD: f64 = 0.75;
+
+/// Asks for every bullet under a heading, taking one typed line at a time and
+/// matching each against the bullets not yet recalled. Every bullet in the
+/// section is marked right or wrong on its own once an empty line is entered.
+/// Hints reveal the next word of the first bullet still missing.
+#[derive(Default)]
+pub struct BulletRecall {
+    question: Option<QuestionRef>,
+    context: QuestionCtx,
+    bullets: Vec<QuestionRef>,
+    recalled: Vec<bool>,
+    // How many words of each bullet the hints have given away
+    revealed: Vec<usize>,
+    // Typed lines that didn't match any bullet
+    unmatched: Vec<String>,
+    finished: bool,
+}
+
+impl BulletRecall {
+    fn word_count(bullet: &QuestionRef) -> usize {
+        bullet.borrow().peek().split_whitespace().count()
+    }
+
+    fn hint(bullet: &QuestionRef, revealed: usize) -> String {
+        let q = bullet.borrow();
+        let words: Vec<_> = q
+            .peek()
+            .split_whitespace()
+            .enumerate()
+            .map(|(i, w)| {
+                if i < revealed {
+                    w.to_string()
+                } else {
+                    "_".repeat(w.chars().count())
+                }
+            })
+            .collect();
+        words.join(" ")
+    }
+
+    fn list(&self, recalled: bool) -> Vec<String> {
+        self.bullets
+            .iter()
+            .zip(&self.recalled)
+            .filter(|(_, &r)| r == recalled)
+            .map(|(b, _)| format!("  - {}", b.borrow().peek()))
+            .collect()
+    }
+}
+
+impl Quiz for BulletRecall {
+    fn set_question(&mut self, q: QuestionRef) {
+        self.bullets = self
+            .context
+            .section
+            .iter()
+            .filter(|b| matches!(b.borrow().data, QuestionVariant::Bullet(_)))
+            .cloned()
+            .collect();
+        if self.bullets.is_empty() {
+            self.bullets.push(Rc::clone(&q));
         }
+        self.recalled = vec![false; self.bullets.len()];
+        self.revealed = vec![0; self.bullets.len()];
+        self.unmatched.clear();
+        self.finished = false;
+        self.question = Some(q);
+    }
+
+    fn set_context(&mut self, ctx: &QuestionCtx) {
+        self.context = ctx.to_owned();
+    }
+
+    fn get_context(&self) -> &QuestionCtx {
+        &self.context
+    }
+
+    fn ask(&self) -> String {
+        let heading = self.context.path.last().map_or("", String::as_str);
+        let count = self.recalled.iter().filter(|&&r| r).count();
+        let mut lines = vec![format!(
+            "List the facts under \"{}\" ({} of {} so far):",
+            heading,
+            count,
+            self.bullets.len()
+        )];
+        for (i, bullet) in self.bullets.iter().enumerate() {
+            if self.recalled[i] {
+                lines.push(format!("  + {}", bullet.borrow().peek()));
+            } else if self.revealed[i] > 0 {
+                lines.push(format!("  ? {}", Self::hint(bullet, self.revealed[i])));
+            }
+        }
+        lines.join("\n")
+    }
+
+    /// There are no choices, the bullets must be typed out
+    fn get_choices(&self) -> &[String] {
+        &[]
+    }
+
+    /// Reveals one more word of the first bullet that hasn't been recalled
+    fn get_hint(&mut self) {
+        let missing = (0..self.bullets.len()).find(|&i| {
+            // Always leave at least one word to be typed
+            !self.recalled[i] && self.revealed[i] + 1 < Self::word_count(&self.bullets[i])
+        });
+        if let Some(i) = missing {
+            self.revealed[i] += 1;
+        }
+    }
+
+    /// Takes one line at a time, marking every bullet when given an empty one
+    fn answer(&mut self, ans: &str) -> Option<(bool, String)> {
+        if !ans.trim().is_empty() {
+            let best = (0..self.bullets.len())
+                .filter(|&i| !self.recalled[i])
+                .map(|i| (i, logic::similarity(ans, self.bullets[i].borrow().peek())))
+                .filter(|&(_, s)| s >= RECALL_THRESHOLD)
+                .max_by(|a, b| a.1.partial_cmp(&b.1).unwrap_or(cmp::Ordering::Equal));
+            match best {
+                Some((i, _)) => self.recalled[i] = true,
+                None => self.unmatched.push(ans.trim().to_string()),
+            }
+            return None;
+        }
+        if self.finished {
+            return None;
+        }
+        self.finished = true;
+        let scheduler = self.context.scheduler.scheduler();
+        for (i, bullet) in self.bullets.iter().enumerate() {
+            let hints = self.revealed[i] as f64 / Self::word_count(bullet).max(1) as f64;
+            bullet
+                .borrow_mut()
+                .record(self.recalled[i], hints, &*scheduler);
+        }
+        let recalled = self.list(true);
+        let missed = self.list(false);
+        let mut answer = vec![format!(
+            "{} of {} recalled",
+            recalled.len(),
+            self.bullets.len()
+        )];
+        for (title, lines) in [("Recalled:", recalled), ("Missed:", missed.clone())].iter() {
+            if !lines.is_empty() {
+                answer.push(title.to_string());
+                answer.extend(lines.iter().cloned());
+            }
+        }
+        if !self.unmatched.is_empty() {
+            answer.push("Not in your notes:".to_string());
+            answer.extend(self.unmatched.iter().map(|l| format!("  - {}", l)));
+        }
+        Some((missed.is_empty(), answer.join("\n")))
+    }
+
+    fn is_multiline(&self) -> bool {
+        true
+    }
+
+    /// Marks every missed bullet as recalled
+    fn i_was_right(&mut self) {
+        let scheduler = self.context.scheduler.scheduler();
+        for (bullet, recalled) in self.bullets.iter().zip(self.recalled.iter_mut()) {
+            if !*recalled {
+                bullet.borrow_mut().override_correct(&*scheduler);
+                *recalled = true;
+            }
+        }
+    }
+
+    fn is_applicable(&self, q: &Question) -> bool {
+        matches!(q.data, QuestionVariant::Bullet(_))
+    }
+}
+
+/// Common words that are never worth blanking out
+const STOP_WORDS: &[&str] = &[
+    "a", "about", "after", "all", "also", "an", "and", "any", "are", "as", "at", "be", "because",
+    "been", "but", "by", "can", "could", "do", "does", "each", "for", "from", "had", "has", "have",
+    "how", "if", "in", "into", "is", "it", "its", "may", "more", "most", "not", "of", "on", "one",
+    "or", "other", "some", "such", "than", "that", "the", "their", "them", "then", "there",
+    "these", "they", "this", "those", "through", "to", "used", "very", "was", "were", "what",
+    "when", "where", "which", "while", "who", "will", "with", "would",
+];
+
+/// Characters that open and close emphasis in the notes
+const EMPHASIS: &[char] = &['*', '/', '_', '=', '~'];
+
+/// A word that could be blanked out of a cloze question
+#[derive(Clone, Debug)]
+struct Keyword {
+    /// The word's byte range in the text, without markup or punctuation
+    range: Range<usize>,
+    word: String,
+    /// How good a keyword this is, before taking rarity into account
+    score: f64,
+}
+
+/// Finds the words worth blanking out of `text`, scoring emphasised words,
+/// numbers, proper nouns and long words the highest
+fn keywords(text: &str) -> Vec<Keyword> {
+    let mut keywords = Vec::new();
+    let mut emphasis: Option<char> = None;
+    let mut sentence_start = true;
+    let mut offset = 0;
+    for token in text.split_whitespace() {
+        let start = offset + text[offset..].find(token).unwrap_or(0);
+        offset = start + token.len();
+        let trimmed = token.trim_start_matches(|c: char| !c.is_alphanumeric());
+        let opener = token[..token.len() - trimmed.len()]
+            .chars()
+            .rev()
+            .find(|c| EMPHASIS.contains(c));
+        if emphasis.is_none() {
+            emphasis = opener;
+        }
+        let emphasised = emphasis.is_some();
+        let core = trimmed.trim_end_matches(|c: char| !c.is_alphanumeric());
+        let rest = &trimmed[core.len()..];
+        if emphasis.is_some_and(|e| rest.contains(e)) {
+            emphasis = None;
+        }
+        let first_in_sentence = sentence_start;
+        sentence_start = rest.contains(['.', '!', '?', ':']);
+        let word = core.to_lowercase();
+        let letters = core.chars().count();
+        let number = core.chars().any(|c| c.is_ascii_digit());
+        if core.is_empty() || (letters < 3 && !number) || STOP_WORDS.contains(&word.as_str()) {
+            continue;
+        }
+        let proper = !first_in_sentence && core.starts_with(char::is_uppercase);
+        let mut score = letters.min(10) as f64 / 10.0;
+        if emphasised {
+            score += 2.0;
+        }
+        if number {
+            score += 2.0;
+        }
+        if proper {
+            score += 1.5;
+        }
+        let core_start = start + (token.len() - trimmed.len());
+        keywords.push(Keyword {
+            range: core_start..core_start + core.len(),
+            word,
+            score,
+        });
+    }
+    keywords
+}
+
+/// Blanks keywords out of a term's definition or a bullet, asking for the
+/// missing words. Words that are rare among the other questions of the quiz
+/// are preferred, unless the notes marked the blanks out themselves. Hints
+/// reveal a letter of every blank at a time.
+#[derive(Default)]
+pub struct Cloze {
+    question: Option<QuestionRef>,
+    context: QuestionCtx,
+    text: String,
+    // The byte ranges of the blanked words, in the order they appear
+    blanks: Vec<Range<usize>>,
+    // How many letters of each blank the hints have given away
+    revealed: usize,
+}
+
+impl Cloze {
+    /// Picks the best keywords of the text to blank out, preferring words that
+    /// are rare among the other questions of the quiz
+    fn pick_blanks(&self) -> Vec<Range<usize>> {
+        let mut frequency: HashMap<String, usize> = HashMap::new();
+        for sibling in &self.context.siblings {
+            let words: HashSet<_> = keywords(sibling.borrow().peek())
+                .into_iter()
+                .map(|k| k.word)
+                .collect();
+            for word in words {
+                *frequency.entry(word).or_default() += 1;
+            }
+        }
+        let mut candidates = keywords(&self.text);
+        // Words found in few other questions are the most telling
+        for keyword in &mut candidates {
+            let count = frequency.get(&keyword.word).copied().unwrap_or(1).max(1);
+            keyword.score += 1.0 / count as f64;
+        }
+        candidates.sort_by(|a, b| {
+            b.score
+                .partial_cmp(&a.score)
+                .unwrap_or(cmp::Ordering::Equal)
+        });
+        let count = (candidates.len() / 10).clamp(1, 3);
+        let mut chosen: Vec<Keyword> = Vec::new();
+        for keyword in candidates {
+            if chosen.len() < count && chosen.iter().all(|k| k.word != keyword.word) {
+                chosen.push(keyword);
+            }
+        }
+        let mut blanks: Vec<_> = chosen.into_iter().map(|k| k.range).collect();
+        blanks.sort_by_key(|r| r.start);
+        blanks
+    }
+
+    /// The text with every blank replaced by underscores, bar revealed letters
+    fn blanked(&self) -> String {
+        let mut blanked = String::new();
+        let mut last = 0;
+        for blank in &self.blanks {
+            blanked += &self.text[last..blank.start];
+            blanked.extend(self.text[blank.clone()].chars().enumerate().map(|(i, c)| {
+                if i < self.revealed {
+                    c
+                } else {
+                    '_'
+                }
+            }));
+            last = blank.end;
+        }
+        blanked + &self.text[last..]
+    }
+
+    /// The text with the typed words filled into the blanks
+    fn filled(&self, words: &[&str]) -> String {
+        let mut filled = String::new();
+        let mut last = 0;
+        for (i, blank) in self.blanks.iter().enumerate() {
+            filled += &self.text[last..blank.start];
+            filled += words.get(i).unwrap_or(&"");
+            last = blank.end;
+        }
+        filled + &self.text[last..]
+    }
+}
+
+impl Quiz for Cloze {
+    fn set_question(&mut self, q: QuestionRef) {
+        self.text = q.borrow().peek().to_string();
+        // Blanks marked out in the notes are used as they are
+        if let QuestionVariant::Cloze(c) = &q.borrow().data {
+            self.blanks = c.hidden().collect();
+        } else {
+            self.blanks = self.pick_blanks();
+        }
+        self.revealed = 0;
+        self.question = Some(q);
+    }
+
+    fn set_context(&mut self, ctx: &QuestionCtx) {
+        self.context = ctx.to_owned();
+    }
+
+    fn get_context(&self) -> &QuestionCtx {
+        &self.context
+    }
+
+    fn ask(&self) -> String {
+        let prompt = match self.question {
+            Some(ref q) => match q.borrow().data {
+                QuestionVariant::Term(_) => format!("{}: ", q.borrow().ask()),
+                _ => String::new(),
+            },
+            None => return String::new(),
+        };
+        let noun = if self.blanks.len() == 1 {
+            "word"
+        } else {
+            "words"
+        };
+        format!(
+            "Fill in the missing {}:\n{}{}",
+            noun,
+            prompt,
+            self.blanked()
+        )
+    }
+
+    /// There are no choices, the missing words must be typed out
+    fn get_choices(&self) -> &[String] {
+        &[]
+    }
+
+    /// Reveals one more letter of every blank
+    fn get_hint(&mut self) {
+        let shortest = self
+            .blanks
+            .iter()
+            .map(|b| self.text[b.clone()].chars().count())
+            .min()
+            .unwrap_or(0);
+        // Always leave at least one letter of each blank to be typed
+        if self.revealed + 1 < shortest {
+            self.revealed += 1;
+        }
+    }
+
+    /// Takes the missing words in order, separated by commas if any of the
+    /// blanks are longer than a word
+    fn answer(&mut self, ans: &str) -> Option<(bool, String)> {
+        let words: Vec<_> = if self.blanks.len() == 1 {
+            vec![ans.trim()]
+        } else if ans.contains(',') {
+            ans.split(',').map(str::trim).collect()
+        } else {
+            ans.split_whitespace().collect()
+        };
+        if words.iter().all(|w| w.is_empty()) {
+            return None;
+        }
+        let letters: usize = self
+            .blanks
+            .iter()
+            .map(|b| self.text[b.clone()].chars().count())
+            .sum();
+        let hints = (self.revealed * self.blanks.len()) as f64 / letters.max(1) as f64;
+        let filled = self.filled(&words);
+        let scheduler = self.context.scheduler.scheduler();
+        let mut q = self.question.as_ref()?.borrow_mut();
+        let (correct, answer) = q.answer(&filled, hints, &*scheduler);
+        Some((correct, answer.to_string()))
+    }
+
+    fn i_was_right(&mut self) {
+        if let Some(ref q) = self.question {
+            q.borrow_mut()
+                .override_correct(&*self.context.scheduler.scheduler())
+        }
+    }
+
+    fn is_applicable(&self, q: &Question) -> bool {
+        match q.data {
+            QuestionVariant::Cloze(_) => true,
+            QuestionVariant::Term(_) | QuestionVariant::Bullet(_) => !keywords(q.peek()).is_empty(),
+            _ => false,
+        }
+    }
+}
+
+/// Shows the prompt of a code block and has its snippet typed out a line at a
+/// time, finishing with an empty line. Hints fill in the next line of the
+/// snippet, so it can be completed rather than written from scratch. Answers
+/// are compared token by token, ignoring whitespace.
+#[derive(Default)]
+pub struct CodeRecall {
+    question: Option<QuestionRef>,
+    context: QuestionCtx,
+    // The lines so far, and whether each was given away by a hint
+    lines: Vec<(bool, String)>,
+    diff: Vec<Diff>,
+    finished: bool,
+}
+
+impl CodeRecall {
+    fn snippet(&self) -> Vec<String> {
+        self.question.as_ref().map_or_else(Vec::new, |q| {
+            q.borrow().peek().lines().map(str::to_string).collect()
+        })
+    }
+}
+
+impl Quiz for CodeRecall {
+    fn set_question(&mut self, q: QuestionRef) {
+        self.lines.clear();
+        self.diff.clear();
+        self.finished = false;
+        self.question = Some(q);
+    }
+
+    fn set_context(&mut self, ctx: &QuestionCtx) {
+        self.context = ctx.to_owned();
+    }
+
+    fn get_context(&self) -> &QuestionCtx {
+        &self.context
+    }
+
+    fn ask(&self) -> String {
+        let q = match self.question {
+            Some(ref q) => q.borrow(),
+            None => return String::new(),
+        };
+        let mut lines = vec![q.ask().into_owned()];
+        lines.extend(
+            self.lines
+                .iter()
+                .map(|(hinted, line)| format!("{} {}", if *hinted { "?" } else { "|" }, line)),
+        );
+        lines.join("\n")
+    }
+
+    /// There are no choices, the snippet must be typed out
+    fn get_choices(&self) -> &[String] {
+        &[]
+    }
+
+    /// Fills in the next line of the snippet
+    fn get_hint(&mut self) {
+        let snippet = self.snippet();
+        // Always leave at least one line to be typed
+        if self.lines.len() + 1 < snippet.len() {
+            self.lines.push((true, snippet[self.lines.len()].clone()));
+        }
+    }
+
+    /// Takes one line at a time, marking the snippet when given an empty one
+    fn answer(&mut self, ans: &str) -> Option<(bool, String)> {
+        if !ans.trim().is_empty() {
+            self.lines.push((false, ans.to_string()));
+            return None;
+        }
+        if self.finished {
+            return None;
+        }
+        self.finished = true;
+        let q = self.question.as_ref()?;
+        let typed: Vec<_> = self.lines.iter().map(|(_, l)| l.as_str()).collect();
+        self.diff = logic::token_diff(&typed.join("\n"), q.borrow().peek());
+        let correct = self.diff.iter().all(|d| matches!(d, Diff::Same(_)));
+        let hinted = self.lines.iter().filter(|(h, _)| *h).count();
+        let hints = hinted as f64 / self.snippet().len().max(1) as f64;
+        let scheduler = self.context.scheduler.scheduler();
+        q.borrow_mut().record(correct, hints, &*scheduler);
+        Some((correct, format!("\n{}", q.borrow().peek())))
+    }
+
+    fn is_multiline(&self) -> bool {
+        true
+    }
+
+    fn get_diff(&self) -> &[Diff] {
+        &self.diff
+    }
+
+    fn i_was_right(&mut self) {
+        if let Some(ref q) = self.question {
+            q.borrow_mut()
+                .override_correct(&*self.context.scheduler.scheduler())
+        }
+    }
+
+    fn is_applicable(&self, q: &Question) -> bool {
+        matches!(q.data, QuestionVariant::Code(_))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::data::{Blank, Bullet, Cloze as ClozeCard, Code, List, Table, Term};
+
+    fn typed_answer(t: &str, d: &str) -> (TypedAnswer, QuestionRef) {
+        let q = Rc::new(RefCell::new(Term::new(t.to_string(), d.to_string())));
+        let mut quiz = TypedAnswer::default();
+        quiz.set_question(Rc::clone(&q));
+        (quiz, q)
+    }
+
+    #[test]
+    fn typed_answer_right() {
+        let (mut quiz, q) = typed_answer("Hallo", "Hello");
+        assert_eq!(quiz.answer(" Hello "), Some((true, "Hello".to_string())));
+        assert_eq!(q.borrow().correct, 1);
+    }
+
+    #[test]
+    fn typed_answer_ignores_empty() {
+        let (mut quiz, q) = typed_answer("Hallo", "Hello");
+        assert_eq!(quiz.answer("  "), None);
+        assert_eq!(q.borrow().seen, 0);
+    }
+
+    #[test]
+    fn typed_answer_to_an_empty_answer() {
+        let (mut quiz, q) = typed_answer("Nothing", "");
+        quiz.get_hint();
+        assert_eq!(quiz.answer("something").map(|(c, _)| c), Some(false));
+        assert_eq!(q.borrow().hints, 0.0);
+    }
+
+    #[test]
+    fn typed_answer_hints() {
+        let (mut quiz, q) = typed_answer("Guten Tag", "Good day");
+        for _ in 0..3 {
+            quiz.get_hint();
+        }
+        assert_eq!(quiz.ask(), "Guten Tag\nHint: Goo_ ___");
+        quiz.answer("Good day");
+        assert_eq!(q.borrow().hints, 3.0 / 8.0);
+    }
+
+    #[test]
+    fn typed_answer_hints_leave_a_character() {
+        let (mut quiz, _) = typed_answer("Ja", "Yes");
+        for _ in 0..10 {
+            quiz.get_hint();
+        }
+        assert_eq!(quiz.ask(), "Ja\nHint: Ye_");
+    }
+
+    fn list_section(steps: &[&str]) -> Vec<QuestionRef> {
+        steps
+            .iter()
+            .enumerate()
+            .map(|(i, s)| {
+                let q = List::new(i as u32 + 1, s.to_string(), Vec::new());
+                Rc::new(RefCell::new(q))
+            })
+            .collect()
+    }
+
+    #[test]
+    fn list_steps_split_on_numbering() {
+        let mut section = list_section(&["A", "B"]);
+        section.extend(list_section(&["C", "D", "E"]));
+        let steps = list_steps(&section[3].borrow(), &section);
+        let items: Vec<_> = steps
+            .iter()
+            .map(|q| q.borrow().peek().to_string())
+            .collect();
+        assert_eq!(items, vec!["C", "D", "E"]);
+    }
+
+    #[test]
+    fn step_order_marks_sequences() {
+        let section = list_section(&["A", "B", "C"]);
+        let mut quiz = StepOrder::default();
+        quiz.set_context(&QuestionCtx {
+            section: section.clone(),
+            ..Default::default()
+        });
+        quiz.set_question(Rc::clone(&section[0]));
+        let right: String = quiz.order.iter().map(|i| (i + 1).to_string()).collect();
+        assert_eq!(quiz.answer("112"), None);
+        assert_eq!(quiz.answer(&right), Some((true, right.clone())));
+        assert_eq!(section[0].borrow().correct, 1);
+    }
+
+    #[test]
+    fn kendall_closeness_bounds() {
+        assert_eq!(kendall_closeness(&[0, 1, 2, 3]), 1.0);
+        assert_eq!(kendall_closeness(&[3, 2, 1, 0]), 0.0);
+        assert_eq!(kendall_closeness(&[1, 0, 2, 3]), 5.0 / 6.0);
+    }
+
+    #[test]
+    fn step_order_partial_credit() {
+        let section = list_section(&["A", "B", "C", "D"]);
+        let mut quiz = StepOrder::default();
+        quiz.set_context(&QuestionCtx {
+            section: section.clone(),
+            ..Default::default()
+        });
+        quiz.set_question(Rc::clone(&section[0]));
+        // Swap the first two steps of the right answer
+        let mut order = quiz.order.clone();
+        order.swap(0, 1);
+        let (correct, _) = quiz.answer(&quiz.format_order(&order)).unwrap();
+        assert!(correct);
+        assert!((section[0].borrow().hints - 1.0 / 6.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn step_order_arrange() {
+        let section = list_section(&["A", "B", "C"]);
+        let mut quiz = StepOrder::default();
+        quiz.set_context(&QuestionCtx {
+            section: section.clone(),
+            ..Default::default()
+        });
+        quiz.set_question(Rc::clone(&section[0]));
+        // Selection sort the steps into place by dragging them around
+        for i in 0..3 {
+            let from = quiz
+                .arrangement
+                .iter()
+                .position(|&s| s == quiz.order[i])
+                .unwrap();
+            quiz.select(from as isize - quiz.selected as isize, false);
+            quiz.select(i as isize - from as isize, true);
+        }
+        assert_eq!(quiz.arrangement, quiz.order);
+        assert_eq!(quiz.answer("").map(|(c, _)| c), Some(true));
+        assert_eq!(section[0].borrow().hints, 0.0);
+    }
+
+    #[test]
+    fn list_step_hints_reveal_details() {
+        let q = List::new(1, "A".to_string(), vec!["Detail".to_string()]);
+        let section = vec![Rc::new(RefCell::new(q))];
+        let mut quiz = ListStep::default();
+        quiz.set_context(&QuestionCtx {
+            section: section.clone(),
+            ..Default::default()
+        });
+        quiz.set_question(Rc::clone(&section[0]));
+        quiz.get_hint();
+        assert_eq!(quiz.ask(), "What is step 1?\nHint: Detail");
+    }
+
+    fn bullet_section(bullets: &[&str]) -> Vec<QuestionRef> {
+        bullets
+            .iter()
+            .map(|b| Rc::new(RefCell::new(Bullet::new(b.to_string()))))
+            .collect()
+    }
+
+    #[test]
+    fn bullet_recall_marks_each_bullet() {
+        let section = bullet_section(&[
+            "The mitochondria is the powerhouse of the cell",
+            "Ribosomes build proteins",
+            "The nucleus holds the DNA",
+        ]);
+        let mut quiz = BulletRecall::default();
+        quiz.set_context(&QuestionCtx {
+            path: vec!["Cells".to_string()],
+            section: section.clone(),
+            ..Default::default()
+        });
+        quiz.set_question(Rc::clone(&section[0]));
+        assert_eq!(quiz.answer("ribosomes bild proteins"), None);
+        assert_eq!(
+            quiz.answer("mitochondria is the powerhouse of the cell"),
+            None
+        );
+        assert_eq!(quiz.answer("golgi apparatus"), None);
+        let (correct, answer) = quiz.answer("").unwrap();
+        assert!(!correct);
+        assert!(answer.starts_with("2 of 3 recalled"));
+        assert!(answer.contains("golgi apparatus"));
+        let stats: Vec<_> = section
+            .iter()
+            .map(|b| (b.borrow().correct, b.borrow().seen))
+            .collect();
+        assert_eq!(stats, vec![(1, 1), (1, 1), (0, 1)]);
+        quiz.i_was_right();
+        assert_eq!(section[2].borrow().correct, 1);
+    }
+
+    #[test]
+    fn bullet_recall_hints_reveal_words() {
+        let section = bullet_section(&["Ribosomes build proteins"]);
+        let mut quiz = BulletRecall::default();
+        quiz.set_context(&QuestionCtx {
+            section: section.clone(),
+            ..Default::default()
+        });
+        quiz.set_question(Rc::clone(&section[0]));
+        for _ in 0..5 {
+            quiz.get_hint();
+        }
+        assert!(quiz.ask().ends_with("? Ribosomes build ________"));
+        quiz.answer("Ribosomes build proteins");
+        assert_eq!(quiz.answer("").map(|(c, _)| c), Some(true));
+        assert!((section[0].borrow().hints - 2.0 / 3.0).abs() < 1e-9);
+    }
+
+    fn cloze(text: &str) -> (Cloze, QuestionRef) {
+        let q = Rc::new(RefCell::new(Bullet::new(text.to_string())));
+        let mut quiz = Cloze::default();
+        quiz.set_question(Rc::clone(&q));
+        (quiz, q)
+    }
+
+    #[test]
+    fn keywords_prefer_emphasis_numbers_and_names() {
+        let best = |text: &str| {
+            let keywords = keywords(text);
+            let best = keywords
+                .iter()
+                .max_by(|a, b| a.score.partial_cmp(&b.score).unwrap())
+                .unwrap();
+            text[best.range.clone()].to_string()
+        };
+        assert_eq!(best("Plants use *light* to grow quickly"), "light");
+        assert_eq!(best("The treaty was signed in 1648 somewhere"), "1648");
+        assert_eq!(best("The capital of France is lovely"), "France");
+        assert_eq!(best("It is an extraordinary idea"), "extraordinary");
+    }
+
+    #[test]
+    fn cloze_blanks_and_fills() {
+        let (mut quiz, q) = cloze("The treaty was signed in 1648.");
+        assert!(quiz.ask().ends_with("The treaty was signed in ____."));
+        quiz.get_hint();
+        assert!(quiz.ask().ends_with("signed in 1___."));
+        assert_eq!(quiz.answer(" "), None);
+        let (correct, answer) = quiz.answer("1648").unwrap();
+        assert!(correct);
+        assert_eq!(answer, "The treaty was signed in 1648.");
+        assert_eq!(q.borrow().hints, 0.25);
+    }
+
+    #[test]
+    fn cloze_marks_wrong_words() {
+        let (mut quiz, q) = cloze("The treaty was signed in 1648.");
+        assert_eq!(quiz.answer("1649").map(|(c, _)| c), Some(false));
+        assert_eq!(q.borrow().correct, 0);
+    }
+
+    #[test]
+    fn cloze_uses_marked_blanks() {
+        let text = "The mitochondria is the powerhouse of the cell".to_string();
+        let blank = |start, end, group| Blank { start, end, group };
+        let blanks = vec![blank(4, 16, 1), blank(24, 34, 2), blank(42, 46, 1)];
+        let card = ClozeCard::new(text, blanks, 1);
+        assert_eq!(card.ask(), "The [...] is the powerhouse of the [...]");
+        let q = Rc::new(RefCell::new(card));
+        let mut quiz = Cloze::default();
+        quiz.set_question(Rc::clone(&q));
+        assert!(quiz
+            .ask()
+            .ends_with("The ____________ is the powerhouse of the ____"));
+        assert_eq!(quiz.answer("mitochondria cell").map(|(c, _)| c), Some(true));
+    }
+
+    fn code_recall(body: &str) -> (CodeRecall, QuestionRef) {
+        let prompt = Some("Print a greeting".to_string());
+        let q = Code::new("rust".to_string(), prompt, body.to_string());
+        let q = Rc::new(RefCell::new(q));
+        let mut quiz = CodeRecall::default();
+        quiz.set_question(Rc::clone(&q));
+        (quiz, q)
+    }
+
+    #[test]
+    fn code_recall_ignores_spacing() {
+        let (mut quiz, q) = code_recall("fn main() {\n    println!(\"hi\");\n}");
+        assert_eq!(quiz.answer("fn main(){"), None);
+        assert_eq!(quiz.answer("println! ( \"hi\" ) ;}"), None);
+        assert!(quiz.answer("").unwrap().0);
+        assert_eq!(q.borrow().correct, 1);
+        assert!(quiz.get_diff().iter().all(|d| matches!(d, Diff::Same(_))));
+    }
+
+    #[test]
+    fn code_recall_hints_fill_lines() {
+        let (mut quiz, q) = code_recall("fn main() {\n    println!(\"hi\");\n}");
+        quiz.get_hint();
+        quiz.get_hint();
+        quiz.get_hint();
+        assert_eq!(
+            quiz.ask(),
+            "Print a greeting\n? fn main() {\n?     println!(\"hi\");"
+        );
+        quiz.answer("}");
+        assert!(quiz.answer("").unwrap().0);
+        assert!((q.borrow().hints - 2.0 / 3.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn code_recall_diffs_wrong_tokens() {
+        let (mut quiz, q) = code_recall("fn main() {\n    println!(\"hi\");\n}");
+        quiz.answer("fn main() {");
+        quiz.answer("println!(\"hi\") ]");
+        assert!(!quiz.answer("").unwrap().0);
+        assert_eq!(q.borrow().correct, 0);
+        let wrong: Vec<_> = quiz
+            .get_diff()
+            .iter()
+            .filter_map(|d| match d {
+                Diff::Missing(t) => Some(format!("-{}", t.text)),
+                Diff::Extra(t) => Some(format!("+{}", t.text)),
+                Diff::Same(_) => None,
+            })
+            .collect();
+        assert_eq!(wrong, ["-;", "-}", "+]"]);
+    }
+
+    #[test]
+    fn multiple_choice_picks_similar_distractors() {
+        let term =
+            |t: &str, d: &str| Rc::new(RefCell::new(Term::new(t.to_string(), d.to_string())));
+        let question = term("Peace of Westphalia", "1648");
+        let cousins = vec![
+            (0, Rc::clone(&question)),
+            (0, term("Congress of Vienna", "1815")),
+            (1, term("Great War", "1914")),
+            (
+                0,
+                term("Treaty", "Something long and wordy about an agreement"),
+            ),
+        ];
+        let mut quiz = MultipleChoice::_new(MCSettings { choices: 2 });
+        quiz.set_context(&QuestionCtx {
+            cousins,
+            ..Default::default()
+        });
+        quiz.set_question(question);
+        assert_eq!(quiz.get_choices().len(), 2);
+        assert!(quiz.get_choices().iter().all(|c| c.len() == 4));
+    }
+
+    #[test]
+    fn multiple_choice_ignores_duplicate_distractors() {
+        let term =
+            |t: &str, d: &str| Rc::new(RefCell::new(Term::new(t.to_string(), d.to_string())));
+        let question = term("Peace of Westphalia", "1648");
+        let cousins = vec![
+            (0, Rc::clone(&question)),
+            (0, term("Thirty Years' War ends", "1648")),
+            (0, term("Congress of Vienna", "1815")),
+            (1, term("Battle of Waterloo", "1815")),
+        ];
+        let mut quiz = MultipleChoice::_new(MCSettings { choices: 4 });
+        quiz.set_context(&QuestionCtx {
+            cousins,
+            ..Default::default()
+        });
+        quiz.set_question(Rc::clone(&question));
+        assert_eq!(quiz.get_choices().len(), 2);
+        let right = quiz.get_choices().iter().position(|c| c == "1648").unwrap();
+        quiz.answer(&(right + 1).to_string());
+        assert_eq!(question.borrow().hints, 0.0);
+    }
+
+    #[test]
+    fn multiple_choice_keeps_to_the_same_kind() {
+        let cells = |cells: &[&str]| cells.iter().map(|c| c.to_string()).collect::<Vec<_>>();
+        let headers = cells(&["Case", "Masculine"]);
+        let rows = vec![cells(&["Nominative", "der"]), cells(&["Dative", "dem"])];
+        let question = Rc::new(RefCell::new(Term::new(
+            "Year".to_string(),
+            "1648".to_string(),
+        )));
+        let cousins = Table::new(&headers, &rows, 0)
+            .into_iter()
+            .map(|q| (0, Rc::new(RefCell::new(q))))
+            .chain(vec![(1, Rc::clone(&question))])
+            .collect();
+        let mut quiz = MultipleChoice::default();
+        quiz.set_context(&QuestionCtx {
+            cousins,
+            ..Default::default()
+        });
+        quiz.set_question(question);
+        assert_eq!(quiz.get_choices(), ["1648"]);
+    }
+
+    #[test]
+    fn dispatcher_finds_cousins_outside_the_section() {
+        let terms = |name: &str, terms: &[(&str, &str)]| {
+            let questions = terms
+                .iter()
+                .map(|(t, d)| Term::new(t.to_string(), d.to_string()))
+                .collect();
+            Section::new(name.to_string(), Vec::new(), questions)
+        };
+        let week = Section::new(
+            "Week 1".to_string(),
+            vec![terms("Monday", &[("Peace of Westphalia", "1648")])],
+            Vec::new(),
+        );
+        let mut tree = Section::new(
+            "History".to_string(),
+            vec![week, terms("Week 2", &[("Congress of Vienna", "1815")])],
+            Vec::new(),
+        );
+        tree.assign_ids();
+        let path = ["Week 1".to_string(), "Monday".to_string()];
+        let mut dispatcher = Dispatcher::new(DSettings::default(), &tree, &path);
+        dispatcher.register_quiz(MultipleChoice::default());
+        let quiz = dispatcher.next().unwrap();
+        let quiz = quiz.borrow();
+        assert_eq!(quiz.get_context().path, ["History", "Week 1", "Monday"]);
+        let cousins: Vec<_> = quiz
+            .get_context()
+            .cousins
+            .iter()
+            .map(|(distance, q)| (*distance, q.borrow().peek().to_string()))
+            .collect();
+        assert!(cousins.contains(&(2, "1815".to_string())));
+        assert_eq!(dispatcher.progress().questions, 1);
+    }
+
+    #[test]
+    fn multiple_choice_draws_table_distractors_from_the_column() {
+        let cells = |cells: &[&str]| cells.iter().map(|c| c.to_string()).collect::<Vec<_>>();
+        let headers = cells(&["Case", "Masculine", "Plural"]);
+        let rows = vec![
+            cells(&["Nominative", "der", "die"]),
+            cells(&["Accusative", "den", "die"]),
+            cells(&["Dative", "dem", "den"]),
+        ];
+        let question = Rc::new(RefCell::new(Table::new(&headers, &rows, 2).remove(1)));
+        assert_eq!(question.borrow().ask(), "Dative, Plural?");
+        let mut quiz = MultipleChoice::default();
+        quiz.set_question(Rc::clone(&question));
+        let mut choices = quiz.get_choices().to_vec();
+        choices.sort();
+        assert_eq!(choices, ["den", "die"]);
+        let right = quiz.get_choices().iter().position(|c| c == "den").unwrap();
+        assert_eq!(
+            quiz.answer(&(right + 1).to_string()).map(|(c, _)| c),
+            Some(true)
+        );
+        assert_eq!(question.borrow().hints, 0.0);
     }
 }
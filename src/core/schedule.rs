@@ -0,0 +1,577 @@
+use super::data::Question;
+use derive_more::{Add, Sum};
+use serde::{Deserialize, Serialize};
+use std::{
+    ops::Deref,
+    str::FromStr,
+    time::{Duration, SystemTime},
+};
+
+const DAY: Duration = Duration::from_secs(24 * 60 * 60);
+
+/// How well a question was answered
+#[derive(Clone, Copy, Serialize, Deserialize, PartialEq, Eq, Debug)]
+pub enum Grade {
+    Wrong,
+    /// Correct, but only with the help of hints
+    Hinted,
+    Right,
+}
+
+#[derive(Clone, Copy, Serialize, Deserialize, PartialEq, Debug)]
+pub struct Review {
+    pub time: SystemTime,
+    pub grade: Grade,
+}
+
+/// What a `Scheduler` remembers about a question between reviews. Each
+/// scheduler has its own variant and treats the others as if the question was
+/// being seen for the first time.
+#[derive(Clone, Copy, Default, Serialize, Deserialize, PartialEq, Debug)]
+pub enum Memory {
+    #[default]
+    New,
+    Leitner {
+        level: u8,
+    },
+    Sm2 {
+        ease: f64,
+        interval: f64, // In days
+        repetitions: u32,
+    },
+    Fsrs {
+        stability: f64, // In days
+        difficulty: f64,
+    },
+}
+
+/// Where a question stands in relation to its next review
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum DueStatus {
+    /// Never reviewed
+    New,
+    /// Not due yet
+    Later,
+    Due,
+    /// Due for more than a day
+    Overdue,
+}
+
+impl DueStatus {
+    pub fn of(question: &Question, now: SystemTime) -> Self {
+        match question.due {
+            None => DueStatus::New,
+            Some(due) if due > now => DueStatus::Later,
+            Some(due) if due + DAY > now => DueStatus::Due,
+            Some(_) => DueStatus::Overdue,
+        }
+    }
+
+    /// Whether a question with this status should be part of a review. New
+    /// questions are, since they're counted alongside the due ones
+    pub fn is_due(self) -> bool {
+        !matches!(self, DueStatus::Later)
+    }
+}
+
+/// Tallies up the `DueStatus` of a set of questions
+#[derive(Clone, Copy, Default, Add, Sum, PartialEq, Eq, Debug)]
+pub struct DueCount {
+    pub new: usize,
+    pub due: usize,
+    pub overdue: usize,
+}
+
+impl DueCount {
+    pub fn of(
+        questions: impl IntoIterator<Item = impl Deref<Target = Question>>,
+        now: SystemTime,
+    ) -> Self {
+        let mut count = DueCount::default();
+        for q in questions {
+            match DueStatus::of(&q, now) {
+                DueStatus::New => count.new += 1,
+                DueStatus::Due => count.due += 1,
+                DueStatus::Overdue => count.overdue += 1,
+                DueStatus::Later => {}
+            }
+        }
+        count
+    }
+
+    pub fn is_empty(&self) -> bool {
+        *self == DueCount::default()
+    }
+}
+
+pub trait Scheduler {
+    /// Takes the memory state and review history of a question from before it
+    /// was answered, returning its new memory state and when it's next due
+    fn schedule(
+        &self,
+        memory: &Memory,
+        history: &[Review],
+        grade: Grade,
+        now: SystemTime,
+    ) -> (Memory, SystemTime);
+
+    /// Rebuilds a memory state from scratch using the whole review history
+    fn replay(&self, history: &[Review]) -> Memory {
+        history
+            .iter()
+            .enumerate()
+            .fold(Memory::New, |memory, (i, r)| {
+                self.schedule(&memory, &history[..i], r.grade, r.time).0
+            })
+    }
+
+    /// The estimated probability of recalling a question right now, if this
+    /// scheduler has a model of memory that can provide one
+    fn recall(&self, _question: &Question, _now: SystemTime) -> Option<f64> {
+        None
+    }
+
+    /// How badly a question needs reviewing, higher meaning more urgent. By
+    /// default, this is how long a question is overdue in days, with new
+    /// questions sitting at zero – behind anything overdue, but ahead of
+    /// everything that isn't due yet.
+    fn urgency(&self, question: &Question, now: SystemTime) -> f64 {
+        match question.due {
+            Some(due) => match now.duration_since(due) {
+                Ok(overdue) => overdue.as_secs_f64() / DAY.as_secs_f64(),
+                Err(e) => -e.duration().as_secs_f64() / DAY.as_secs_f64(),
+            },
+            None => 0.0,
+        }
+    }
+}
+
+/// Questions move up a level each time they are answered correctly and down
+/// one when they are missed. Each level doubles the time until the question is
+/// due again.
+pub struct Leitner;
+
+impl Leitner {
+    // FIXME: Make the range of min and max levels configurable
+    pub const MAX_LEVEL: u8 = 10;
+
+    /// How long a question at some level waits after a right answer
+    pub fn interval(level: u8) -> Duration {
+        match level {
+            0 => Duration::from_secs(0),
+            level => DAY * 2u32.pow(u32::from(level) - 1),
+        }
+    }
+}
+
+impl Scheduler for Leitner {
+    fn schedule(
+        &self,
+        memory: &Memory,
+        history: &[Review],
+        grade: Grade,
+        now: SystemTime,
+    ) -> (Memory, SystemTime) {
+        let level = match *memory {
+            Memory::New => 0,
+            Memory::Leitner { level } => level,
+            _ => return self.schedule(&self.replay(history), history, grade, now),
+        };
+        let level = match grade {
+            Grade::Wrong => level.saturating_sub(1),
+            // Hints leave the level where it was, but the question should come
+            // back around soon
+            Grade::Hinted => level,
+            Grade::Right => (level + 1).min(Self::MAX_LEVEL),
+        };
+        let due = match grade {
+            Grade::Right => now + Self::interval(level),
+            _ => now,
+        };
+        (Memory::Leitner { level }, due)
+    }
+}
+
+/// The SuperMemo 2 algorithm, which adjusts an "ease" for each question that
+/// scales the interval between reviews
+pub struct Sm2;
+
+impl Sm2 {
+    const INITIAL_EASE: f64 = 2.5;
+    const MIN_EASE: f64 = 1.3;
+
+    /// Maps grades onto the 0–5 quality scale used by SM-2
+    fn quality(grade: Grade) -> f64 {
+        match grade {
+            Grade::Wrong => 1.0,
+            Grade::Hinted => 3.0,
+            Grade::Right => 5.0,
+        }
+    }
+}
+
+impl Scheduler for Sm2 {
+    fn schedule(
+        &self,
+        memory: &Memory,
+        history: &[Review],
+        grade: Grade,
+        now: SystemTime,
+    ) -> (Memory, SystemTime) {
+        let (ease, interval, repetitions) = match *memory {
+            Memory::New => (Self::INITIAL_EASE, 0.0, 0),
+            Memory::Sm2 {
+                ease,
+                interval,
+                repetitions,
+            } => (ease, interval, repetitions),
+            _ => return self.schedule(&self.replay(history), history, grade, now),
+        };
+        let q = Self::quality(grade);
+        let ease = (ease + 0.1 - (5.0 - q) * (0.08 + (5.0 - q) * 0.02)).max(Self::MIN_EASE);
+        let (interval, repetitions) = if q < 3.0 {
+            (1.0, 0)
+        } else {
+            let interval = match repetitions {
+                0 => 1.0,
+                1 => 6.0,
+                _ => (interval * ease).round(),
+            };
+            (interval, repetitions + 1)
+        };
+        let memory = Memory::Sm2 {
+            ease,
+            interval,
+            repetitions,
+        };
+        (memory, now + DAY.mul_f64(interval))
+    }
+}
+
+/// A model of memory in the style of FSRS (Free Spaced Repetition Scheduler).
+/// Each question has a stability – the number of days until the chance of
+/// recalling it falls to 90% – and a difficulty from 1 to 10. Reviews are
+/// scheduled for when the chance of recall drops to the desired retention.
+pub struct Fsrs {
+    pub retention: f64,
+}
+
+impl Default for Fsrs {
+    fn default() -> Self {
+        Self { retention: 0.9 }
+    }
+}
+
+impl Fsrs {
+    // The default weights of FSRS v4.5, fitted to a large collection of reviews
+    const W: [f64; 17] = [
+        0.4872, 1.4003, 3.7145, 13.8206, 5.1618, 1.2298, 0.8975, 0.031, 1.6474, 0.1367, 1.0461,
+        2.1072, 0.0793, 0.3246, 1.587, 0.2272, 2.8755,
+    ];
+    const DECAY: f64 = -0.5;
+    // Chosen so that recall is exactly 90% after `stability` days
+    const FACTOR: f64 = 19.0 / 81.0;
+
+    /// Maps grades onto the 1–4 rating scale used by FSRS (leaving out "Easy")
+    fn rating(grade: Grade) -> f64 {
+        match grade {
+            Grade::Wrong => 1.0,
+            Grade::Hinted => 2.0,
+            Grade::Right => 3.0,
+        }
+    }
+
+    fn retrievability(elapsed: f64, stability: f64) -> f64 {
+        (1.0 + Self::FACTOR * elapsed / stability).powf(Self::DECAY)
+    }
+
+    fn initial_difficulty(rating: f64) -> f64 {
+        (Self::W[4] - (rating - 3.0) * Self::W[5]).clamp(1.0, 10.0)
+    }
+
+    /// The number of days until recall falls to the desired retention
+    fn interval(&self, stability: f64) -> f64 {
+        stability / Self::FACTOR * (self.retention.powf(1.0 / Self::DECAY) - 1.0)
+    }
+}
+
+impl Scheduler for Fsrs {
+    fn schedule(
+        &self,
+        memory: &Memory,
+        history: &[Review],
+        grade: Grade,
+        now: SystemTime,
+    ) -> (Memory, SystemTime) {
+        let w = &Self::W;
+        let rating = Self::rating(grade);
+        let (stability, difficulty) = match *memory {
+            Memory::New => (w[rating as usize - 1], Self::initial_difficulty(rating)),
+            Memory::Fsrs {
+                stability,
+                difficulty,
+            } => {
+                let elapsed = history.last().map_or(0.0, |r| days_between(r.time, now));
+                let r = Self::retrievability(elapsed, stability);
+                let stability = if grade == Grade::Wrong {
+                    let forget = w[11]
+                        * difficulty.powf(-w[12])
+                        * ((stability + 1.0).powf(w[13]) - 1.0)
+                        * (w[14] * (1.0 - r)).exp();
+                    forget.min(stability)
+                } else {
+                    let hard_penalty = if grade == Grade::Hinted { w[15] } else { 1.0 };
+                    let growth = w[8].exp()
+                        * (11.0 - difficulty)
+                        * stability.powf(-w[9])
+                        * ((w[10] * (1.0 - r)).exp() - 1.0)
+                        * hard_penalty;
+                    stability * (growth + 1.0)
+                };
+                // Nudge the difficulty, reverting a little towards the default
+                let difficulty = difficulty - w[6] * (rating - 3.0);
+                let difficulty = w[7] * Self::initial_difficulty(3.0) + (1.0 - w[7]) * difficulty;
+                (stability, difficulty.clamp(1.0, 10.0))
+            }
+            _ => return self.schedule(&self.replay(history), history, grade, now),
+        };
+        let memory = Memory::Fsrs {
+            stability,
+            difficulty,
+        };
+        (memory, now + DAY.mul_f64(self.interval(stability)))
+    }
+
+    fn recall(&self, question: &Question, now: SystemTime) -> Option<f64> {
+        match question.memory {
+            Memory::Fsrs { stability, .. } => {
+                let last = question.history.last()?;
+                Some(Self::retrievability(
+                    days_between(last.time, now),
+                    stability,
+                ))
+            }
+            _ => None,
+        }
+    }
+
+    /// Questions are as urgent as how far their chance of recall has dropped
+    /// below the desired retention
+    fn urgency(&self, question: &Question, now: SystemTime) -> f64 {
+        self.recall(question, now)
+            .map_or(0.0, |r| self.retention - r)
+    }
+}
+
+fn days_between(earlier: SystemTime, later: SystemTime) -> f64 {
+    later
+        .duration_since(earlier)
+        .unwrap_or_default()
+        .as_secs_f64()
+        / DAY.as_secs_f64()
+}
+
+/// The schedulers that can be picked from the settings
+#[derive(Clone, Copy, Default, Serialize, Deserialize, PartialEq, Debug)]
+pub enum SchedulerKind {
+    #[default]
+    Leitner,
+    Sm2,
+    Fsrs {
+        retention: f64,
+    },
+}
+
+impl SchedulerKind {
+    pub fn scheduler(self) -> Box<dyn Scheduler> {
+        match self {
+            SchedulerKind::Leitner => Box::new(Leitner),
+            SchedulerKind::Sm2 => Box::new(Sm2),
+            SchedulerKind::Fsrs { retention } => Box::new(Fsrs { retention }),
+        }
+    }
+}
+
+impl FromStr for SchedulerKind {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "leitner" => Ok(SchedulerKind::Leitner),
+            "sm2" | "sm-2" => Ok(SchedulerKind::Sm2),
+            "fsrs" => Ok(SchedulerKind::Fsrs {
+                retention: Fsrs::default().retention,
+            }),
+            _ => Err(format!("'{}' isn't a known scheduler", s)),
+        }
+    }
+}
+
+/// Reads a desired retention for the fsrs scheduler, which has to be a chance
+/// strictly between 0 and 1 for reviews to ever be scheduled
+pub fn parse_retention(s: &str) -> Result<f64, String> {
+    match s.parse::<f64>() {
+        Ok(retention) if retention > 0.0 && retention < 1.0 => Ok(retention),
+        Ok(_) => Err(format!(
+            "the retention has to be between 0 and 1, not {}",
+            s
+        )),
+        Err(e) => Err(format!("'{}' isn't a number: {}", s, e)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn run(scheduler: &dyn Scheduler, grades: &[Grade]) -> (Memory, SystemTime) {
+        let now = SystemTime::now();
+        let mut memory = Memory::New;
+        let mut due = now;
+        for &grade in grades {
+            let (m, d) = scheduler.schedule(&memory, &[], grade, now);
+            memory = m;
+            due = d;
+        }
+        (memory, due)
+    }
+
+    #[test]
+    fn leitner_due_doubles() {
+        let now = SystemTime::now();
+        let (memory, due) = Leitner.schedule(&Memory::Leitner { level: 2 }, &[], Grade::Right, now);
+        assert_eq!(memory, Memory::Leitner { level: 3 });
+        assert_eq!(due, now + DAY * 4);
+    }
+
+    #[test]
+    fn leitner_wrong_is_due_now() {
+        let now = SystemTime::now();
+        let (_, due) = Leitner.schedule(&Memory::Leitner { level: 2 }, &[], Grade::Wrong, now);
+        assert_eq!(due, now);
+    }
+
+    #[test]
+    fn sm2_intervals() {
+        let right = [Grade::Right; 3];
+        match run(&Sm2, &right).0 {
+            Memory::Sm2 {
+                interval,
+                repetitions,
+                ..
+            } => {
+                assert_eq!(repetitions, 3);
+                assert!(interval > 6.0);
+            }
+            m => panic!("Expected an SM-2 memory, got {:?}", m),
+        }
+    }
+
+    #[test]
+    fn sm2_wrong_resets() {
+        let grades = [Grade::Right, Grade::Right, Grade::Wrong];
+        match run(&Sm2, &grades).0 {
+            Memory::Sm2 {
+                ease,
+                interval,
+                repetitions,
+            } => {
+                assert_eq!(repetitions, 0);
+                assert_eq!(interval, 1.0);
+                assert!(ease < Sm2::INITIAL_EASE + 0.2);
+            }
+            m => panic!("Expected an SM-2 memory, got {:?}", m),
+        }
+    }
+
+    #[test]
+    fn switching_schedulers_replays_history() {
+        let now = SystemTime::now();
+        let history = vec![
+            Review {
+                time: now,
+                grade: Grade::Right,
+            };
+            2
+        ];
+        let (memory, _) = Leitner.schedule(&Leitner.replay(&history), &history, Grade::Right, now);
+        let (sm2, _) = Sm2.schedule(&memory, &history, Grade::Right, now);
+        assert_eq!(memory, Memory::Leitner { level: 3 });
+        assert!(matches!(sm2, Memory::Sm2 { repetitions: 3, .. }));
+    }
+
+    #[test]
+    fn fsrs_interval_matches_stability() {
+        let now = SystemTime::now();
+        let (memory, due) = Fsrs::default().schedule(&Memory::New, &[], Grade::Right, now);
+        match memory {
+            Memory::Fsrs { stability, .. } => {
+                let interval = due.duration_since(now).unwrap().as_secs_f64() / 86400.0;
+                assert!((interval - stability).abs() < 1e-6);
+            }
+            m => panic!("Expected an FSRS memory, got {:?}", m),
+        }
+    }
+
+    #[test]
+    fn fsrs_lower_retention_waits_longer() {
+        let now = SystemTime::now();
+        let strict = Fsrs { retention: 0.95 };
+        let (_, strict_due) = strict.schedule(&Memory::New, &[], Grade::Right, now);
+        let (_, lax_due) = Fsrs { retention: 0.8 }.schedule(&Memory::New, &[], Grade::Right, now);
+        assert!(lax_due > strict_due);
+    }
+
+    #[test]
+    fn fsrs_stability_follows_grades() {
+        let fsrs = Fsrs::default();
+        let start = SystemTime::now();
+        let later = start + DAY * 3;
+        let history = [Review {
+            time: start,
+            grade: Grade::Right,
+        }];
+        let (first, _) = fsrs.schedule(&Memory::New, &[], Grade::Right, start);
+        let stability = |m| match m {
+            Memory::Fsrs { stability, .. } => stability,
+            m => panic!("Expected an FSRS memory, got {:?}", m),
+        };
+        let (right, _) = fsrs.schedule(&first, &history, Grade::Right, later);
+        let (hinted, _) = fsrs.schedule(&first, &history, Grade::Hinted, later);
+        let (wrong, _) = fsrs.schedule(&first, &history, Grade::Wrong, later);
+        assert!(stability(right) > stability(hinted));
+        assert!(stability(hinted) > stability(first));
+        assert!(stability(wrong) < stability(first));
+    }
+
+    #[test]
+    fn due_status_follows_due_time() {
+        let now = SystemTime::now();
+        let mut q = crate::core::data::Term::new("q".to_string(), "a".to_string());
+        assert_eq!(DueStatus::of(&q, now), DueStatus::New);
+        q.due = Some(now + DAY);
+        assert_eq!(DueStatus::of(&q, now), DueStatus::Later);
+        q.due = Some(now);
+        assert_eq!(DueStatus::of(&q, now), DueStatus::Due);
+        q.due = Some(now - DAY * 2);
+        assert_eq!(DueStatus::of(&q, now), DueStatus::Overdue);
+        assert!(DueStatus::New.is_due());
+        assert!(!DueStatus::Later.is_due());
+    }
+
+    #[test]
+    fn parse_scheduler_kind() {
+        assert_eq!("SM-2".parse(), Ok(SchedulerKind::Sm2));
+        assert_eq!("leitner".parse(), Ok(SchedulerKind::Leitner));
+        assert_eq!("fsrs".parse(), Ok(SchedulerKind::Fsrs { retention: 0.9 }));
+        assert!("nope".parse::<SchedulerKind>().is_err());
+    }
+
+    #[test]
+    fn retention_must_be_a_chance() {
+        assert_eq!(parse_retention("0.85"), Ok(0.85));
+        for bad in &["0", "-0.5", "1", "1.5", "NaN", "high"] {
+            assert!(parse_retention(bad).is_err(), "{} was accepted", bad);
+        }
+    }
+}
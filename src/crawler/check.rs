@@ -0,0 +1,121 @@
+use super::{
+    data::{CrawlError, Crawler},
+    parse::{capture, Remainder},
+};
+use std::ops::Range;
+
+/// A stretch of the notes picked out by one of the recipe's rules
+#[derive(Debug, PartialEq, Eq)]
+pub struct Coverage {
+    pub range: Range<usize>,
+    pub rule: &'static str,
+}
+
+impl Crawler {
+    /// Compiles every regex built from the recipe, returning all of the errors
+    pub fn check_regexes(&self) -> Vec<CrawlError> {
+        vec![
+            self.title_regexes().err(),
+            self.section_regexes().err(),
+            self.term_regex().err(),
+            self.list_regexes().err(),
+            self.bullet_regex().err(),
+            self.cloze_regex().err(),
+            self.code_regex().err(),
+            self.table_regexes().err(),
+            self.exclude_regexes().err(),
+        ]
+        .into_iter()
+        .flatten()
+        .collect()
+    }
+
+    /// Finds the stretches of the notes that each rule picks out, in the same
+    /// order that `parse_sections` would
+    pub fn coverage(&self, src: &str) -> Result<Vec<Coverage>, CrawlError> {
+        let mut covered = Vec::new();
+        if let Some((title, author, date)) = &self.patterns()?.title {
+            let found = [Some(title), author.as_ref(), date.as_ref()];
+            for (re, rule) in found.iter().zip(&["title", "author", "date"]) {
+                if let Some((start, end)) = re.and_then(|re| re.find(src)) {
+                    covered.push(Coverage {
+                        range: start..end,
+                        rule,
+                    });
+                }
+            }
+        }
+        let (text, codes) = self.parse_codes(src)?;
+        covered.extend(codes.into_iter().map(|(range, _)| Coverage {
+            range,
+            rule: "code",
+        }));
+        let (text, excluded) = self.exclude(&text)?;
+        covered.extend(excluded.into_iter().map(|range| Coverage {
+            range,
+            rule: "excluded",
+        }));
+        self.cover_sections(&text, 0, &mut covered)?;
+        covered.sort_by_key(|c| c.range.start);
+        Ok(covered)
+    }
+
+    fn cover_sections(
+        &self,
+        src: &str,
+        offset: usize,
+        covered: &mut Vec<Coverage>,
+    ) -> Result<(), CrawlError> {
+        if let Some((sect_re, quest_re)) = &self.patterns()?.section {
+            for caps in sect_re.captures_iter(src) {
+                let start = caps.pos(0).map_or(0, |(start, _)| start);
+                let heading_end = caps.pos(2).map_or(start, |(_, end)| end);
+                covered.push(Coverage {
+                    range: offset + start..offset + heading_end,
+                    rule: "section",
+                });
+                let body = capture(&caps, "section", 3)?;
+                let body_start = offset + caps.pos(3).map_or(0, |(start, _)| start);
+                self.cover_sections(body, body_start, covered)?;
+                if let Some((start, end)) = quest_re.captures(body).and_then(|c| c.pos(1)) {
+                    self.cover_questions(&body[start..end], body_start + start, covered)?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn cover_questions(
+        &self,
+        src: &str,
+        offset: usize,
+        covered: &mut Vec<Coverage>,
+    ) -> Result<(), CrawlError> {
+        let patterns = self.patterns()?;
+        let rules = [
+            ("table", patterns.table.as_ref().map(|(re, _, _)| re)),
+            ("term", patterns.term.as_ref()),
+            ("list", patterns.list.as_ref().map(|(re, _)| re)),
+            ("bullet", patterns.bullet.as_ref()),
+        ];
+        let mut remainder = Remainder::new(src, offset);
+        for (rule, re) in rules.iter() {
+            if let Some(re) = re {
+                let taken: Vec<_> = re
+                    .find_iter(&remainder.text)
+                    .map(|(start, end)| start..end)
+                    .collect();
+                for range in &taken {
+                    covered.extend(
+                        remainder
+                            .origin(range)
+                            .into_iter()
+                            .map(|range| Coverage { range, rule }),
+                    );
+                }
+                remainder = remainder.remove(&taken);
+            }
+        }
+        Ok(())
+    }
+}
@@ -1,8 +1,9 @@
 /// This module holds the data types and implementations used by the crawler,
 /// but not the core data types of the program. All of the code for the
 /// intermediate data representation lives in the core data module.
-use serde::Deserialize;
-use std::error::Error;
+use onig::Regex;
+use serde::{de, Deserialize, Deserializer};
+use std::{cell::OnceCell, error::Error, fmt, io, path::PathBuf};
 
 #[derive(Debug, Deserialize)]
 pub enum ReflowStrategy {
@@ -14,13 +15,67 @@ pub enum ReflowStrategy {
 /// This struct holds all of the configuration data that is parsed from the TOML
 #[derive(Debug, Deserialize)]
 pub struct Crawler {
-    pub title: String,
+    /// What the recipe is for, which used to be given as `title`
+    #[serde(default)]
+    pub description: String,
+    #[serde(default, deserialize_with = "title_table")]
+    pub title: Option<TitleConfig>,
     pub exts: Vec<String>,
     pub flow: ReflowStrategy, // Should this be in each subsection?
     pub section: Option<SectionConfig>,
     pub term: Option<TermConfig>,
     pub list: Option<ListConfig>,
     pub bullet: Option<BulletConfig>,
+    pub cloze: Option<ClozeConfig>,
+    pub code: Option<CodeConfig>,
+    pub table: Option<TableConfig>,
+    #[serde(default)]
+    pub exclude: Vec<ExcludeConfig>,
+    /// The regexes built from the tables above, compiled on first use
+    #[serde(skip)]
+    pub(super) patterns: OnceCell<Patterns>,
+}
+
+/// The regexes for the title, author and date of a file
+pub(super) type TitlePatterns = (Regex, Option<Regex>, Option<Regex>);
+
+/// The regexes for a whole table, the dividers between its cells and the rows
+/// that only divide other rows
+pub(super) type TablePatterns = (Regex, Regex, Regex);
+
+/// Every regex that a recipe builds, so that they're only compiled once
+#[derive(Debug)]
+pub(super) struct Patterns {
+    pub title: Option<TitlePatterns>,
+    pub section: Option<(Regex, Regex)>,
+    pub term: Option<Regex>,
+    pub list: Option<(Regex, Regex)>,
+    pub bullet: Option<Regex>,
+    pub cloze: Option<Regex>,
+    pub code: Option<Regex>,
+    pub table: Option<TablePatterns>,
+    pub exclude: Vec<Regex>,
+}
+
+/// This struct holds the regexes for the details at the top of a file of
+/// notes. The first capture group of the first match of each is taken, with
+/// the title shown in place of the file's name.
+#[derive(Debug, Deserialize)]
+pub struct TitleConfig {
+    pub pattern: String,
+    pub author: Option<String>,
+    pub date: Option<String>,
+}
+
+/// Reads the `[title]` table, ignoring the string that recipes used to
+/// describe themselves with in its place
+fn title_table<'de, D: Deserializer<'de>>(
+    deserializer: D,
+) -> Result<Option<TitleConfig>, D::Error> {
+    match Option::<toml::Value>::deserialize(deserializer)? {
+        Some(toml::Value::String(_)) | None => Ok(None),
+        Some(table) => table.try_into().map(Some).map_err(de::Error::custom),
+    }
 }
 
 #[derive(Debug, Deserialize)]
@@ -58,9 +113,137 @@ pub struct BulletConfig {
     pub terminator: String,
 }
 
+/// This struct holds the regex components for marking blanks within the text
+/// of terms and bullets. Blanks without a `group` are all hidden together.
+#[derive(Debug, Deserialize)]
+pub struct ClozeConfig {
+    pub leader: String,
+    pub group: Option<String>,
+    pub separator: Option<String>,
+    pub body: String,
+    pub terminator: String,
+}
+
+/// This struct holds the regex components for code blocks. The block starts
+/// on a line matching `leader`, followed by its `language`, and its body runs
+/// up to the `terminator`. The line just before it, if it matches `prompt`
+/// after the `prompt_leader`, is what the block is asked with.
+#[derive(Debug, Deserialize)]
+pub struct CodeConfig {
+    pub prompt_leader: Option<String>,
+    pub prompt: Option<String>,
+    pub leader: String,
+    pub language: String,
+    pub body: String,
+    pub terminator: String,
+}
+
+/// This struct holds the regexes for pipe tables, made of the lines that start
+/// with the `leader`. Each line is a row, cut into cells by the `separator`,
+/// unless it matches `rule` and only divides the rows. The first row holds the
+/// headers, and the first cell of every other row is its key.
+#[derive(Debug, Deserialize)]
+pub struct TableConfig {
+    pub leader: String,
+    pub separator: String,
+    pub rule: String,
+}
+
+/// This enum holds a regex for text to leave out of the notes before they're
+/// crawled. A block runs from a match of `begin` to the end of the line that
+/// `end` matches on, and a line from a match of `line` to the end of its line.
+#[derive(Debug, Deserialize)]
+#[serde(untagged)]
+pub enum ExcludeConfig {
+    Block { begin: String, end: String },
+    Line { line: String },
+}
+
+/// Everything that can go wrong while crawling notes with a recipe
+#[derive(Debug)]
+pub enum CrawlError {
+    /// The recipe wasn't valid TOML or was missing fields
+    Recipe(toml::de::Error),
+    /// A recipe regex didn't compile, blamed on a field like `term.separator`
+    Regex { field: String, source: onig::Error },
+    /// The notes couldn't be read
+    Io { path: PathBuf, source: io::Error },
+    /// A list numeral matched by the recipe wasn't a number
+    Numeral { text: String, offset: usize },
+    /// A recipe regex matched without filling in one of its capture groups
+    MissingCapture { field: String, group: usize },
+    /// A directory had no files with any of the recipe's `exts`
+    NoNotes { path: PathBuf, exts: Vec<String> },
+    /// No recipe was known for a file's extension
+    NoRecipe { path: PathBuf },
+    /// More than one recipe was known for a file's extension
+    AmbiguousRecipe { path: PathBuf, names: Vec<String> },
+    /// A recipe from the recipe directory was broken
+    BadRecipe {
+        path: PathBuf,
+        source: Box<CrawlError>,
+    },
+}
+
+impl fmt::Display for CrawlError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CrawlError::Recipe(source) => write!(f, "couldn't read the recipe: {}", source),
+            CrawlError::Regex { field, source } => {
+                write!(f, "invalid regex in recipe field `{}`: {}", field, source)
+            }
+            CrawlError::Io { path, source } => {
+                write!(f, "couldn't read {}: {}", path.display(), source)
+            }
+            CrawlError::Numeral { text, offset } => write!(
+                f,
+                "list numeral {:?} at byte {} isn't a number, check the recipe's `list.numerals`",
+                text, offset
+            ),
+            CrawlError::MissingCapture { field, group } => write!(
+                f,
+                "the `{}` regex matched without capture group {}, check the recipe",
+                field, group
+            ),
+            CrawlError::NoNotes { path, exts } => write!(
+                f,
+                "no notes ending in .{} were found in {}",
+                exts.join(" or ."),
+                path.display()
+            ),
+            CrawlError::NoRecipe { path } => {
+                write!(f, "no recipe is known for {}", path.display())
+            }
+            CrawlError::AmbiguousRecipe { path, names } => write!(
+                f,
+                "{} could be crawled with any of {}, so one has to be picked by name",
+                path.display(),
+                names.join(", ")
+            ),
+            CrawlError::BadRecipe { path, source } => {
+                write!(f, "in recipe {}: {}", path.display(), source)
+            }
+        }
+    }
+}
+
+impl Error for CrawlError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            CrawlError::Recipe(source) => Some(source),
+            CrawlError::Regex { source, .. } => Some(source),
+            CrawlError::Io { source, .. } => Some(source),
+            CrawlError::BadRecipe { source, .. } => Some(source.as_ref()),
+            _ => None,
+        }
+    }
+}
+
 impl Crawler {
-    // It's low priority, but this method should be tested somewhere
-    pub fn new(toml_str: &str) -> Result<Self, impl Error> {
-        toml::from_str(&toml_str)
+    /// Reads a recipe and compiles all of its regexes up front
+    pub fn new(toml_str: &str) -> Result<Self, CrawlError> {
+        let crawler: Self = toml::from_str(toml_str).map_err(CrawlError::Recipe)?;
+        crawler.patterns()?;
+        Ok(crawler)
     }
 }
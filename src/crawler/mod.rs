@@ -1,6 +1,8 @@
 /// This module is responsible for the parsing of notes into a format
 /// understandable by the core module. The code here should make no assumptions
 /// about how the data is processed thereafter.
+pub mod check;
 pub mod data;
 pub mod parse;
+pub mod registry;
 pub mod util;
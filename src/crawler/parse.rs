@@ -1,35 +1,327 @@
-use super::{data::Crawler, util};
+use super::{
+    data::{CrawlError, Crawler, ExcludeConfig, Patterns, TablePatterns, TitlePatterns},
+    util,
+};
 use crate::core::data::*;
-use onig::Regex;
-use std::{fs, mem, path::Path};
+use onig::{Captures, Regex};
+use std::{fs, iter, mem, ops::Range, path::Path};
+
+/// Compiles a regex built from the fields of a recipe table, each given in the
+/// form it's spliced into the pattern. If it doesn't compile, the first field
+/// that doesn't compile on its own takes the blame.
+fn compile(table: &str, fields: &[(&str, &str)], pattern: &str) -> Result<Regex, CrawlError> {
+    Regex::new(pattern).map_err(|source| {
+        let field = fields
+            .iter()
+            .find(|(_, re)| Regex::new(re).is_err())
+            .map_or(table.to_owned(), |(name, _)| format!("{}.{}", table, name));
+        CrawlError::Regex { field, source }
+    })
+}
+
+/// Gets a capture group that the recipe table's regex should always fill
+pub(super) fn capture<'t>(
+    caps: &Captures<'t>,
+    table: &str,
+    group: usize,
+) -> Result<&'t str, CrawlError> {
+    caps.at(group).ok_or_else(|| CrawlError::MissingCapture {
+        field: table.to_owned(),
+        group,
+    })
+}
+
+/// The text left once some rules have taken their matches out, remembering
+/// where each piece of it came from in the notes
+pub(super) struct Remainder {
+    pub text: String,
+    // The start of each piece in `text`, and where that piece starts in the notes
+    pieces: Vec<(usize, usize)>,
+}
+
+impl Remainder {
+    pub fn new(text: &str, offset: usize) -> Self {
+        Self {
+            text: text.to_owned(),
+            pieces: vec![(0, offset)],
+        }
+    }
+
+    /// Where the piece at an index ends in the remaining text
+    fn piece_end(&self, piece: usize) -> usize {
+        self.pieces.get(piece + 1).map_or(self.text.len(), |p| p.0)
+    }
+
+    /// The index of the piece holding an offset of the remaining text
+    fn piece_at(&self, pos: usize) -> usize {
+        self.pieces
+            .partition_point(|&(start, _)| start <= pos)
+            .saturating_sub(1)
+    }
+
+    /// Maps a range of the remaining text back to ranges of the notes
+    pub fn origin(&self, range: &Range<usize>) -> Vec<Range<usize>> {
+        let mut ranges = Vec::new();
+        for piece in self.piece_at(range.start)..self.pieces.len() {
+            let (start, origin) = self.pieces[piece];
+            if start >= range.end {
+                break;
+            }
+            let (from, to) = (range.start.max(start), range.end.min(self.piece_end(piece)));
+            if from < to {
+                ranges.push(origin + from - start..origin + to - start);
+            }
+        }
+        ranges
+    }
+
+    /// The range of the notes from where a range of the remaining text starts
+    /// to where it ends
+    pub fn span(&self, range: &Range<usize>) -> Option<Range<usize>> {
+        let end = range.end.min(self.text.len());
+        if range.start >= end {
+            return None;
+        }
+        // Pieces are never empty, so both ends fall inside one
+        let locate = |pos| {
+            let (start, origin) = self.pieces[self.piece_at(pos)];
+            origin + pos - start
+        };
+        Some(locate(range.start)..locate(end - 1) + 1)
+    }
+
+    /// Takes some sorted, non-overlapping ranges out of the remaining text
+    pub fn remove(&self, taken: &[Range<usize>]) -> Self {
+        let mut kept = Vec::new();
+        let mut last = 0;
+        for range in taken {
+            kept.push(last..range.start);
+            last = range.end;
+        }
+        kept.push(last..self.text.len());
+        let mut text = String::new();
+        let mut pieces = Vec::new();
+        // Both the kept ranges and the pieces are in order, so one pass does
+        let mut piece = 0;
+        for range in kept.iter().filter(|r| !r.is_empty()) {
+            let mut pos = range.start;
+            while self.piece_end(piece) <= pos {
+                piece += 1;
+            }
+            while pos < range.end {
+                let (start, origin) = self.pieces[piece];
+                let end = self.piece_end(piece).min(range.end);
+                pieces.push((text.len(), origin + pos - start));
+                text += &self.text[pos..end];
+                if end == self.piece_end(piece) {
+                    piece += 1;
+                }
+                pos = end;
+            }
+        }
+        Self { text, pieces }
+    }
+}
+
+/// Turns byte offsets into the notes into lines and columns
+struct Lines<'a> {
+    src: &'a str,
+    // The notes as crawled, with anything excluded blanked out
+    crawled: &'a str,
+    // The byte offset that each line starts at
+    starts: Vec<usize>,
+}
+
+impl<'a> Lines<'a> {
+    fn new(src: &'a str, crawled: &'a str) -> Self {
+        let starts = iter::once(0)
+            .chain(src.match_indices('\n').map(|(i, _)| i + 1))
+            .collect();
+        Self {
+            src,
+            crawled,
+            starts,
+        }
+    }
+
+    fn position(&self, offset: usize) -> (usize, usize) {
+        let line = match self.starts.binary_search(&offset) {
+            Ok(line) => line,
+            Err(line) => line - 1,
+        };
+        let column = self.src[self.starts[line]..offset].chars().count() + 1;
+        (line + 1, column)
+    }
+
+    /// Locates a range of the notes from its first character to its last,
+    /// leaving out any whitespace (or excluded text) it ends with
+    fn locate(&self, range: Range<usize>) -> Location {
+        let text = self.crawled[range.clone()].trim_end();
+        let last = text.char_indices().next_back().map_or(0, |(i, _)| i);
+        Location {
+            file: None,
+            start: self.position(range.start),
+            end: self.position(range.start + last),
+        }
+    }
+
+    /// Locates the whole of a match in some remaining text
+    fn locate_match(&self, src: &Remainder, caps: &Captures) -> Option<Location> {
+        let (start, end) = caps.pos(0)?;
+        src.span(&(start..end)).map(|range| self.locate(range))
+    }
+}
+
+/// Blanks out a range of some text with spaces, keeping its line breaks so
+/// that every byte after it stays where it was
+fn blank_out(text: &mut String, range: Range<usize>) {
+    let blank: String = text[range.clone()]
+        .chars()
+        .map(|c| {
+            if c == '\n' {
+                "\n".to_owned()
+            } else {
+                " ".repeat(c.len_utf8())
+            }
+        })
+        .collect();
+    text.replace_range(range, &blank);
+}
+
+/// A code block found in the notes, with the range of the notes it came from
+type CodeBlock = (Range<usize>, Question);
+
+/// Marks where some questions were crawled from
+fn place(
+    questions: impl IntoIterator<Item = Question>,
+    location: Option<Location>,
+) -> impl Iterator<Item = Question> {
+    questions.into_iter().map(move |mut q| {
+        q.location = location.clone();
+        q
+    })
+}
 
 impl Crawler {
-    /// Parse flashcards from str
-    fn parse_terms(&self, src: &str) -> (String, Vec<Question>) {
+    /// Gets the compiled regexes, compiling them if this is the first time
+    pub(super) fn patterns(&self) -> Result<&Patterns, CrawlError> {
+        if let Some(patterns) = self.patterns.get() {
+            return Ok(patterns);
+        }
+        let patterns = Patterns {
+            title: self.title_regexes()?,
+            section: self.section_regexes()?,
+            term: self.term_regex()?,
+            list: self.list_regexes()?,
+            bullet: self.bullet_regex()?,
+            cloze: self.cloze_regex()?,
+            code: self.code_regex()?,
+            table: self.table_regexes()?,
+            exclude: self.exclude_regexes()?,
+        };
+        Ok(self.patterns.get_or_init(|| patterns))
+    }
+
+    /// The regexes for each stretch of text to leave out of the notes
+    pub(super) fn exclude_regexes(&self) -> Result<Vec<Regex>, CrawlError> {
+        self.exclude
+            .iter()
+            .enumerate()
+            .map(|(i, rules)| {
+                let table = format!("exclude[{}]", i);
+                match rules {
+                    ExcludeConfig::Block { begin, end } => {
+                        let re_str = format!("(?:{})[\\s\\S]*?(?:{}).*", begin, end);
+                        compile(&table, &[("begin", begin), ("end", end)], &re_str)
+                    }
+                    ExcludeConfig::Line { line } => {
+                        compile(&table, &[("line", line)], &format!("(?:{}).*", line))
+                    }
+                }
+            })
+            .collect()
+    }
+
+    /// Blanks out everything that the recipe excludes from some notes, keeping
+    /// the line breaks so that every byte is where it was. The ranges blanked
+    /// out are returned too.
+    pub(super) fn exclude(&self, src: &str) -> Result<(String, Vec<Range<usize>>), CrawlError> {
+        let mut text = src.to_owned();
+        let mut excluded = Vec::new();
+        for re in &self.patterns()?.exclude {
+            let found: Vec<_> = re
+                .find_iter(&text)
+                .map(|(start, end)| start..end)
+                .filter(|range| !range.is_empty())
+                .collect();
+            for range in found {
+                blank_out(&mut text, range.clone());
+                excluded.push(range);
+            }
+        }
+        Ok((text, excluded))
+    }
+
+    /// The regexes for the title, author and date of a file
+    pub(super) fn title_regexes(&self) -> Result<Option<TitlePatterns>, CrawlError> {
+        if let Some(rules) = self.title.as_ref() {
+            let field = |name, re: &str| compile("title", &[(name, re)], re);
+            let optional = |name, re: &Option<String>| re.as_deref().map(|re| field(name, re));
+            Ok(Some((
+                field("pattern", &rules.pattern)?,
+                optional("author", &rules.author).transpose()?,
+                optional("date", &rules.date).transpose()?,
+            )))
+        } else {
+            Ok(None)
+        }
+    }
+
+    /// The regexes for a whole section and for the questions before its first
+    /// subsection
+    pub(super) fn section_regexes(&self) -> Result<Option<(Regex, Regex)>, CrawlError> {
+        if let Some(rules) = self.section.as_ref() {
+            // The marker is only ever used escaped, so it's checked that way too
+            let marker = format!("\\{}+", rules.marker);
+            let sect_re_str = format!(
+                "(^{} )({})\\s({})((?=^\\1)|\\z)",
+                marker, rules.name, rules.body
+            );
+            let quest_re_str = format!("({})(^{} |\\z)", rules.body, marker);
+            let fields = [
+                ("marker", marker.as_str()),
+                ("name", &rules.name),
+                ("body", &rules.body),
+            ];
+            let sect_re = compile("section", &fields, &sect_re_str)?;
+            let quest_re = compile("section", &fields, &quest_re_str)?;
+            Ok(Some((sect_re, quest_re)))
+        } else {
+            Ok(None)
+        }
+    }
+
+    pub(super) fn term_regex(&self) -> Result<Option<Regex>, CrawlError> {
         if let Some(rules) = self.term.as_ref() {
             let re_str = format!(
                 "{}({}){}({}){}",
                 rules.leader, rules.term, rules.separator, rules.definition, rules.terminator,
             );
-            let matches = Regex::new(&re_str).unwrap();
-            let mut remainder = String::from(src);
-            let mut questions = Vec::new();
-            for caps in matches.captures_iter(src) {
-                remainder = remainder.replace(caps.at(0).unwrap(), "");
-                let mut term = util::reflow_string(&self.flow, caps.at(1).unwrap());
-                let mut definition = util::reflow_string(&self.flow, caps.at(2).unwrap());
-                if rules.flipped.is_some() && rules.flipped.unwrap() {
-                    mem::swap(&mut term, &mut definition);
-                }
-                questions.push(Term::new(term, definition));
-            }
-            (remainder, questions)
+            let fields = [
+                ("leader", rules.leader.as_str()),
+                ("term", &rules.term),
+                ("separator", &rules.separator),
+                ("definition", &rules.definition),
+                ("terminator", &rules.terminator),
+            ];
+            compile("term", &fields, &re_str).map(Some)
         } else {
-            (src.to_owned(), Vec::new())
+            Ok(None)
         }
     }
 
-    fn parse_lists(&self, src: &str) -> (String, Vec<Question>) {
+    /// The regexes for a whole list item and for each of its details
+    pub(super) fn list_regexes(&self) -> Result<Option<(Regex, Regex)>, CrawlError> {
         if let Some(rules) = self.list.as_ref() {
             let re_str = format!(
                 "({}){} ({})\\s*({}{})*{}",
@@ -44,101 +336,494 @@ impl Crawler {
                 "{}({}){}",
                 rules.sub_leader, rules.body, rules.sub_terminator
             );
+            let fields = [
+                ("numerals", rules.numerals.as_str()),
+                ("leader", &rules.leader),
+                ("body", &rules.body),
+                ("sub_leader", &rules.sub_leader),
+                ("sub_terminator", &rules.sub_terminator),
+                ("terminator", &rules.terminator),
+            ];
             // Yikes, this needs a lot of refactoring
-            let matches = Regex::new(&re_str).unwrap();
-            let sub_matches = Regex::new(&sub_re_str).unwrap();
-            let mut remainder = String::from(src);
-            let mut questions = Vec::new();
+            let matches = compile("list", &fields, &re_str)?;
+            let sub_matches = compile("list", &fields, &sub_re_str)?;
+            Ok(Some((matches, sub_matches)))
+        } else {
+            Ok(None)
+        }
+    }
+
+    pub(super) fn bullet_regex(&self) -> Result<Option<Regex>, CrawlError> {
+        if let Some(rules) = self.bullet.as_ref() {
+            let re_str = format!("{}({}){}", rules.leader, rules.body, rules.terminator,);
+            let fields = [
+                ("leader", rules.leader.as_str()),
+                ("body", &rules.body),
+                ("terminator", &rules.terminator),
+            ];
+            compile("bullet", &fields, &re_str).map(Some)
+        } else {
+            Ok(None)
+        }
+    }
+
+    pub(super) fn cloze_regex(&self) -> Result<Option<Regex>, CrawlError> {
+        if let Some(rules) = self.cloze.as_ref() {
+            let group = rules.group.as_deref().unwrap_or("");
+            let separator = rules.separator.as_deref().unwrap_or("");
+            let re_str = format!(
+                "{}({}){}({}){}",
+                rules.leader, group, separator, rules.body, rules.terminator,
+            );
+            let fields = [
+                ("leader", rules.leader.as_str()),
+                ("group", group),
+                ("separator", separator),
+                ("body", &rules.body),
+                ("terminator", &rules.terminator),
+            ];
+            compile("cloze", &fields, &re_str).map(Some)
+        } else {
+            Ok(None)
+        }
+    }
+
+    pub(super) fn code_regex(&self) -> Result<Option<Regex>, CrawlError> {
+        if let Some(rules) = self.code.as_ref() {
+            let prompt_leader = rules.prompt_leader.as_deref().unwrap_or("");
+            let prompt = match rules.prompt.as_deref() {
+                Some(prompt) => format!("(?:^{}({})[ \\t]*\\n)?", prompt_leader, prompt),
+                None => "()".to_owned(),
+            };
+            let re_str = format!(
+                "{}^{}({}).*\\n({}){}",
+                prompt, rules.leader, rules.language, rules.body, rules.terminator,
+            );
+            let fields = [
+                ("prompt_leader", prompt_leader),
+                ("prompt", rules.prompt.as_deref().unwrap_or("")),
+                ("leader", &rules.leader),
+                ("language", &rules.language),
+                ("body", &rules.body),
+                ("terminator", &rules.terminator),
+            ];
+            compile("code", &fields, &re_str).map(Some)
+        } else {
+            Ok(None)
+        }
+    }
+
+    /// The regexes for a whole table, the dividers between its cells and the
+    /// rows that only divide other rows
+    pub(super) fn table_regexes(&self) -> Result<Option<TablePatterns>, CrawlError> {
+        if let Some(rules) = self.table.as_ref() {
+            let fields = [
+                ("leader", rules.leader.as_str()),
+                ("separator", &rules.separator),
+                ("rule", &rules.rule),
+            ];
+            let table_re_str = format!("(?:^(?:{}).*(?:\\n|\\z))+", rules.leader);
+            let table_re = compile("table", &fields, &table_re_str)?;
+            let separator_re = compile("table", &fields, &rules.separator)?;
+            let rule_re = compile("table", &fields, &format!("\\A(?:{})\\z", rules.rule))?;
+            Ok(Some((table_re, separator_re, rule_re)))
+        } else {
+            Ok(None)
+        }
+    }
+
+    /// Takes the code blocks out of some notes before anything else is
+    /// crawled, so that nothing in them is mistaken for a heading or a
+    /// question. The notes are returned with the blocks blanked out. Blocks
+    /// that the recipe excludes, like those in a drawer or a comment, are left
+    /// in place for `exclude` to blank out.
+    pub(super) fn parse_codes(&self, src: &str) -> Result<(String, Vec<CodeBlock>), CrawlError> {
+        let mut text = src.to_owned();
+        let mut codes = Vec::new();
+        if let Some(matches) = &self.patterns()?.code {
+            let lines = Lines::new(src, src);
+            let (_, excluded) = self.exclude(src)?;
+            for caps in matches.captures_iter(src) {
+                let (start, end) = match caps.pos(0) {
+                    Some(pos) if pos.0 < pos.1 => pos,
+                    _ => continue,
+                };
+                // Anything excluded inside the block itself doesn't count
+                let hidden = excluded.iter().any(|range| {
+                    range.start < end
+                        && start < range.end
+                        && (range.start < start || range.end > end)
+                });
+                if hidden {
+                    continue;
+                }
+                let prompt = caps
+                    .at(1)
+                    .map(|p| util::reflow_string(&self.flow, p))
+                    .filter(|p| !p.is_empty());
+                let language = capture(&caps, "code", 2)?.trim().to_owned();
+                let body = util::dedent(capture(&caps, "code", 3)?);
+                let mut question = Code::new(language, prompt, body);
+                question.location = Some(lines.locate(start..end));
+                codes.push((start..end, question));
+            }
+        }
+        for (range, _) in &codes {
+            blank_out(&mut text, range.clone());
+        }
+        Ok((text, codes))
+    }
+
+    /// Strips cloze markup out of some text, returning the bare text and a
+    /// question for every group of blanks marked in it
+    fn parse_clozes(&self, src: &str) -> Result<(String, Vec<Question>), CrawlError> {
+        if let Some(matches) = &self.patterns()?.cloze {
+            let mut text = String::new();
+            let mut blanks = Vec::new();
+            let mut last = 0;
             for caps in matches.captures_iter(src) {
-                remainder = remainder.replace(caps.at(0).unwrap(), "");
+                let (start, end) = caps.pos(0).unwrap_or_default();
+                let body = capture(&caps, "cloze", 2)?;
+                text += &src[last..start];
+                blanks.push(Blank {
+                    start: text.len(),
+                    end: text.len() + body.len(),
+                    group: caps.at(1).and_then(|g| g.parse().ok()).unwrap_or(1),
+                });
+                text += body;
+                last = end;
+            }
+            text += &src[last..];
+            let mut groups: Vec<_> = blanks.iter().map(|b| b.group).collect();
+            groups.sort_unstable();
+            groups.dedup();
+            let questions = groups
+                .into_iter()
+                .map(|g| Cloze::new(text.clone(), blanks.clone(), g))
+                .collect();
+            Ok((text, questions))
+        } else {
+            Ok((src.to_owned(), Vec::new()))
+        }
+    }
+
+    /// Parses tables into a question for every cell that has a row key and a
+    /// column header, each placed at its row
+    fn parse_tables(
+        &self,
+        src: Remainder,
+        lines: &Lines,
+    ) -> Result<(Remainder, Vec<Question>), CrawlError> {
+        if let Some((matches, separator, rule)) = &self.patterns()?.table {
+            let mut taken = Vec::new();
+            let mut questions = Vec::new();
+            for (start, end) in matches.find_iter(&src.text) {
+                taken.push(start..end);
+                let mut rows = Vec::new();
+                let mut row_start = start;
+                for line in src.text[start..end].split_terminator('\n') {
+                    let range = row_start..row_start + line.len();
+                    row_start = range.end + 1;
+                    if rule.is_match(line.trim_end()) {
+                        continue;
+                    }
+                    let mut cells: Vec<_> = separator.split(line).map(|c| c.trim()).collect();
+                    // Rows start and usually end with a divider
+                    if cells.last() == Some(&"") {
+                        cells.pop();
+                    }
+                    if cells.first() == Some(&"") {
+                        cells.remove(0);
+                    }
+                    rows.push((range, cells));
+                }
+                if rows.len() < 2 || rows[0].1.len() < 2 {
+                    continue;
+                }
+                let headers: Vec<_> = rows[0].1.iter().map(|c| c.to_string()).collect();
+                let body: Vec<_> = rows[1..]
+                    .iter()
+                    .map(|(_, cells)| {
+                        (0..headers.len())
+                            .map(|i| cells.get(i).map_or(String::new(), |c| c.to_string()))
+                            .collect()
+                    })
+                    .collect();
+                for (row, (range, _)) in rows[1..].iter().enumerate() {
+                    let location = src.span(range).map(|r| lines.locate(r));
+                    questions.extend(place(Table::new(&headers, &body, row), location));
+                }
+            }
+            Ok((src.remove(&taken), questions))
+        } else {
+            Ok((src, Vec::new()))
+        }
+    }
+
+    /// Parse flashcards from str
+    fn parse_terms(
+        &self,
+        src: Remainder,
+        lines: &Lines,
+    ) -> Result<(Remainder, Vec<Question>), CrawlError> {
+        if let Some(matches) = &self.patterns()?.term {
+            let flipped = self.term.as_ref().and_then(|r| r.flipped) == Some(true);
+            let mut taken = Vec::new();
+            let mut questions = Vec::new();
+            for caps in matches.captures_iter(&src.text) {
+                taken.extend(caps.pos(0).map(|(start, end)| start..end));
+                let mut term = util::reflow_string(&self.flow, capture(&caps, "term", 1)?);
+                let mut definition = util::reflow_string(&self.flow, capture(&caps, "term", 2)?);
+                if flipped {
+                    mem::swap(&mut term, &mut definition);
+                }
+                let (definition, clozes) = self.parse_clozes(&definition)?;
+                let location = lines.locate_match(&src, &caps);
+                questions.extend(place(
+                    iter::once(Term::new(term, definition)),
+                    location.clone(),
+                ));
+                questions.extend(place(clozes, location));
+            }
+            Ok((src.remove(&taken), questions))
+        } else {
+            Ok((src, Vec::new()))
+        }
+    }
+
+    fn parse_lists(
+        &self,
+        src: Remainder,
+        lines: &Lines,
+    ) -> Result<(Remainder, Vec<Question>), CrawlError> {
+        if let Some((matches, sub_matches)) = &self.patterns()?.list {
+            let mut taken = Vec::new();
+            let mut questions = Vec::new();
+            for caps in matches.captures_iter(&src.text) {
+                taken.extend(caps.pos(0).map(|(start, end)| start..end));
                 let details = match caps.at(3) {
                     Some(b) => sub_matches
                         .captures_iter(b)
-                        .map(|sub_caps| util::reflow_string(&self.flow, sub_caps.at(1).unwrap()))
-                        .collect(),
+                        .map(|sub_caps| {
+                            Ok(util::reflow_string(
+                                &self.flow,
+                                capture(&sub_caps, "list", 1)?,
+                            ))
+                        })
+                        .collect::<Result<_, CrawlError>>()?,
                     None => Vec::new(),
                 };
-                questions.push(List::new(
-                    caps.at(1).unwrap().parse().unwrap(),
-                    util::reflow_string(&self.flow, caps.at(2).unwrap()),
+                let numeral = capture(&caps, "list", 1)?;
+                let order = numeral.parse().map_err(|_| CrawlError::Numeral {
+                    text: numeral.to_owned(),
+                    offset: caps
+                        .pos(1)
+                        .and_then(|(start, end)| src.origin(&(start..end)).first().cloned())
+                        .map_or(0, |range| range.start),
+                })?;
+                let question = List::new(
+                    order,
+                    util::reflow_string(&self.flow, capture(&caps, "list", 2)?),
                     details,
-                ));
+                );
+                questions.extend(place(Some(question), lines.locate_match(&src, &caps)));
             }
-            (remainder, questions)
+            Ok((src.remove(&taken), questions))
         } else {
-            (src.to_owned(), Vec::new())
+            Ok((src, Vec::new()))
         }
     }
 
-    fn parse_bullets(&self, src: &str) -> (String, Vec<Question>) {
-        if let Some(rules) = self.bullet.as_ref() {
-            let re_str = format!("{}({}){}", rules.leader, rules.body, rules.terminator,);
-            // There is some duplication to fix here!
-            let matches = Regex::new(&re_str).unwrap();
-            let mut remainder = String::from(src);
+    fn parse_bullets(
+        &self,
+        src: Remainder,
+        lines: &Lines,
+    ) -> Result<(Remainder, Vec<Question>), CrawlError> {
+        // There is some duplication to fix here!
+        if let Some(matches) = &self.patterns()?.bullet {
+            let mut taken = Vec::new();
             let mut questions = Vec::new();
-            for caps in matches.captures_iter(src) {
-                remainder = remainder.replace(caps.at(0).unwrap(), "");
-                questions.push(Bullet::new(util::reflow_string(
-                    &self.flow,
-                    caps.at(1).unwrap(),
-                )));
+            for caps in matches.captures_iter(&src.text) {
+                taken.extend(caps.pos(0).map(|(start, end)| start..end));
+                let body = util::reflow_string(&self.flow, capture(&caps, "bullet", 1)?);
+                // Bullets with blanks marked out are only asked as clozes
+                let found = match self.parse_clozes(&body)? {
+                    (body, clozes) if clozes.is_empty() => vec![Bullet::new(body)],
+                    (_, clozes) => clozes,
+                };
+                questions.extend(place(found, lines.locate_match(&src, &caps)));
             }
-            (remainder, questions)
+            Ok((src.remove(&taken), questions))
         } else {
-            (src.to_owned(), Vec::new())
+            Ok((src, Vec::new()))
         }
     }
 
-    pub fn parse_questions(&self, src: &str) -> Vec<Question> {
+    /// Parse the questions in some notes, ignoring any sections
+    pub fn parse_questions(&self, src: &str) -> Result<Vec<Question>, CrawlError> {
+        let (text, codes) = self.parse_codes(src)?;
+        let (text, _) = self.exclude(&text)?;
+        let mut questions = self.questions_in(Remainder::new(&text, 0), &Lines::new(src, &text))?;
+        questions.extend(codes.into_iter().map(|(_, q)| q));
+        Ok(questions)
+    }
+
+    fn questions_in(&self, src: Remainder, lines: &Lines) -> Result<Vec<Question>, CrawlError> {
         let mut questions = Vec::new();
-        let (src, chunk) = self.parse_terms(src);
+        let (src, chunk) = self.parse_tables(src, lines)?;
         questions.extend(chunk);
-        let (src, chunk) = self.parse_lists(&src);
+        let (src, chunk) = self.parse_terms(src, lines)?;
         questions.extend(chunk);
-        let (_, chunk) = self.parse_bullets(&src);
+        let (src, chunk) = self.parse_lists(src, lines)?;
         questions.extend(chunk);
-        questions
+        let (_, chunk) = self.parse_bullets(src, lines)?;
+        questions.extend(chunk);
+        Ok(questions)
     }
 
-    /// Get section
-    pub fn parse_sections(&self, src: &str) -> Vec<Section> {
-        if let Some(rules) = self.section.as_ref() {
-            let sect_re_str = format!(
-                "(^\\{}+ )({})\\s({})((?=^\\1)|\\z)",
-                rules.marker, rules.name, rules.body
-            );
-            let quest_re_str = format!("({})(^\\{}+ |\\z)", rules.body, rules.marker);
-            let sect_re = Regex::new(&sect_re_str).unwrap();
-            let quest_re = Regex::new(&quest_re_str).unwrap();
+    /// Get sections. Their questions only get stable ids once the whole tree
+    /// is known, from `Recipes::parse_path`
+    pub fn parse_sections(&self, src: &str) -> Result<Vec<Section>, CrawlError> {
+        let (text, codes) = self.parse_codes(src)?;
+        let (text, _) = self.exclude(&text)?;
+        self.parse_section_tree(&text, 0, &Lines::new(src, &text), &codes)
+    }
+
+    /// Parses the sections in some text that starts `offset` bytes into the
+    /// notes, giving each the code blocks that start among its questions
+    fn parse_section_tree(
+        &self,
+        src: &str,
+        offset: usize,
+        lines: &Lines,
+        codes: &[CodeBlock],
+    ) -> Result<Vec<Section>, CrawlError> {
+        if let Some((sect_re, quest_re)) = &self.patterns()?.section {
             sect_re
                 .captures_iter(src)
                 .map(|caps| {
-                    let name = caps.at(2).unwrap();
-                    let body = caps.at(3).unwrap();
-                    let children = self.parse_sections(body);
-                    let question_body = quest_re.captures(body).unwrap().at(1).unwrap();
-                    let questions = self.parse_questions(question_body);
-                    Section::new(name.to_owned(), children, questions)
+                    let name = capture(&caps, "section", 2)?;
+                    let body = capture(&caps, "section", 3)?;
+                    let body_offset = offset + caps.pos(3).map_or(0, |(start, _)| start);
+                    let children = self.parse_section_tree(body, body_offset, lines, codes)?;
+                    let (question_body, question_offset) = match quest_re.captures(body) {
+                        Some(quest_caps) => (
+                            capture(&quest_caps, "section", 1)?,
+                            body_offset + quest_caps.pos(1).map_or(0, |(start, _)| start),
+                        ),
+                        None => {
+                            return Err(CrawlError::MissingCapture {
+                                field: "section".to_owned(),
+                                group: 1,
+                            })
+                        }
+                    };
+                    let mut questions =
+                        self.questions_in(Remainder::new(question_body, question_offset), lines)?;
+                    let question_range = question_offset..question_offset + question_body.len();
+                    questions.extend(
+                        codes
+                            .iter()
+                            .filter(|(range, _)| question_range.contains(&range.start))
+                            .map(|(_, q)| q.clone()),
+                    );
+                    let mut section = Section::new(name.to_owned(), children, questions);
+                    section.location = caps.pos(0).map(|(start, end)| {
+                        let range = offset + start..offset + end;
+                        let mut location = lines.locate(range.clone());
+                        // Code blocks are blanked out, but still end their sections
+                        for (code, q) in codes {
+                            if let (true, Some(code_location)) =
+                                (range.contains(&code.start), &q.location)
+                            {
+                                location.end = location.end.max(code_location.end);
+                            }
+                        }
+                        location
+                    });
+                    Ok(section)
                 })
                 .collect()
         } else {
-            Vec::new()
+            Ok(Vec::new())
+        }
+    }
+
+    /// Finds the title of a file of notes and the rest of its metadata, if the
+    /// recipe says how to
+    fn parse_title(&self, src: &str) -> Result<Option<Metadata>, CrawlError> {
+        if let Some((title, author, date)) = &self.patterns()?.title {
+            let first = |re: &Regex| {
+                let found = re.captures(src)?.at(1)?.trim();
+                Some(found.to_owned()).filter(|f| !f.is_empty())
+            };
+            Ok(Some(Metadata {
+                title: first(title),
+                author: author.as_ref().and_then(first),
+                date: date.as_ref().and_then(first),
+            }))
+        } else {
+            Ok(None)
         }
     }
 
     // This feels a tad out of place
-    pub fn parse_file(&self, filename: &str) -> Section {
-        let src = fs::read_to_string(filename).unwrap();
-        Section::new(
-            Path::new(filename)
-                .file_name()
-                .unwrap()
-                .to_str()
-                .unwrap()
-                .to_owned(),
-            self.parse_sections(&src),
-            Vec::new(),
-        )
+    /// Crawls a file of notes into a section named after it, leaving the ids
+    /// to `Recipes::parse_path` like `parse_sections` does
+    pub fn parse_file(&self, filename: &str) -> Result<Section, CrawlError> {
+        let src = fs::read_to_string(filename).map_err(|source| CrawlError::Io {
+            path: filename.into(),
+            source,
+        })?;
+        // The title is only kept for show, so editing it leaves the ids alone
+        let name = Path::new(filename)
+            .file_name()
+            .map_or(filename.into(), |name| name.to_string_lossy())
+            .into_owned();
+        let mut root = Section::new(name, self.parse_sections(&src)?, Vec::new());
+        root.metadata = self.parse_title(&src)?;
+        root.location = Some(Lines::new(&src, &src).locate(0..src.len()));
+        root.set_file(filename);
+        Ok(root)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn remainder_maps_back_to_notes() {
+        let remainder = Remainder::new("aaXXbbYYcc", 100);
+        let remainder = remainder.remove(&[2..4, 6..8]);
+        assert_eq!(remainder.text, "aabbcc");
+        assert_eq!(
+            remainder.origin(&(1..5)),
+            vec![101..102, 104..106, 108..109]
+        );
+        let remainder = remainder.remove(std::slice::from_ref(&(2..4)));
+        assert_eq!(remainder.text, "aacc");
+        assert_eq!(remainder.origin(&(0..4)), vec![100..102, 108..110]);
+        assert_eq!(remainder.span(&(1..3)), Some(101..109));
+        assert_eq!(remainder.span(&(2..2)), None);
+        let remainder = remainder.remove(&[0..1, 2..4]);
+        assert_eq!(remainder.text, "a");
+        assert_eq!(remainder.origin(&(0..1)), vec![101..102]);
+        let remainder = remainder.remove(std::slice::from_ref(&(0..1)));
+        assert_eq!(remainder.span(&(0..1)), None);
+    }
+
+    #[test]
+    fn lines_and_columns() {
+        let src = "one\ntwo  \n\nfür drei\n";
+        let lines = Lines::new(src, src);
+        assert_eq!(lines.position(0), (1, 1));
+        assert_eq!(lines.position(4), (2, 1));
+        assert_eq!(lines.position(10), (3, 1));
+        // Columns count characters, not bytes
+        assert_eq!(lines.position(16), (4, 5));
+        let location = lines.locate(4..11);
+        assert_eq!((location.start, location.end), ((2, 1), (2, 3)));
+        assert_eq!(location.to_string(), "line 2:1-2:3");
     }
 }
@@ -0,0 +1,286 @@
+use super::data::{CrawlError, Crawler};
+use crate::core::data::Section;
+use std::{
+    collections::HashSet,
+    fs,
+    io::ErrorKind,
+    path::{Path, PathBuf},
+};
+
+/// The recipes built into the binary, by name. Each extension should only be
+/// claimed by one of them, so that there's always a default.
+const BUILT_IN: &[(&str, &str)] = &[
+    ("borg", include_str!("../../confs/borg.toml")),
+    ("markdown", include_str!("../../confs/markdown.toml")),
+];
+
+/// Anything that can pick the recipe to crawl a file of notes with
+pub trait Recipes {
+    /// The recipe for a file, or `None` if it isn't a file of notes. A file is
+    /// `explicit` when it was named directly instead of found in a directory
+    fn recipe_for(&self, file: &Path, explicit: bool) -> Result<Option<&Crawler>, CrawlError>;
+
+    /// Every extension that there's a recipe for
+    fn exts(&self) -> Vec<String>;
+
+    /// Crawls a file of notes or, given a directory, every file of notes below
+    /// it. Folders and files both become sections of the tree. This is the
+    /// only place ids are stamped, once every section in their paths is known.
+    fn parse_path(&self, path: &str) -> Result<Section, CrawlError> {
+        let mut root = if !Path::new(path).is_dir() {
+            self.recipe_for(Path::new(path), true)?
+                .ok_or_else(|| CrawlError::NoRecipe { path: path.into() })?
+                .parse_file(path)?
+        } else {
+            parse_dir(self, Path::new(path), &mut HashSet::new())?.ok_or_else(|| {
+                CrawlError::NoNotes {
+                    path: path.into(),
+                    exts: self.exts(),
+                }
+            })?
+        };
+        root.assign_ids();
+        Ok(root)
+    }
+}
+
+impl Recipes for Crawler {
+    fn recipe_for(&self, file: &Path, explicit: bool) -> Result<Option<&Crawler>, CrawlError> {
+        let is_notes = file
+            .extension()
+            .is_some_and(|ext| self.exts.iter().any(|e| ext == e.as_str()));
+        Ok(Some(self).filter(|_| explicit || is_notes))
+    }
+
+    fn exts(&self) -> Vec<String> {
+        self.exts.clone()
+    }
+}
+
+/// Crawls the notes in a directory, in order of name, leaving out hidden
+/// files and any directories without notes. Directories already `visited`
+/// (through a symlink, say) are left out too, so links can't loop forever
+fn parse_dir(
+    recipes: &(impl Recipes + ?Sized),
+    dir: &Path,
+    visited: &mut HashSet<PathBuf>,
+) -> Result<Option<Section>, CrawlError> {
+    let io_error = |source| CrawlError::Io {
+        path: dir.into(),
+        source,
+    };
+    if !visited.insert(dir.canonicalize().map_err(io_error)?) {
+        return Ok(None);
+    }
+    let mut entries = fs::read_dir(dir)
+        .map_err(io_error)?
+        .map(|entry| entry.map(|e| e.path()))
+        .collect::<Result<Vec<PathBuf>, _>>()
+        .map_err(io_error)?;
+    entries.sort();
+    let mut children = Vec::new();
+    for path in entries {
+        let hidden = path
+            .file_name()
+            .is_some_and(|name| name.to_string_lossy().starts_with('.'));
+        if hidden {
+            continue;
+        } else if path.is_dir() {
+            children.extend(parse_dir(recipes, &path, visited)?);
+        } else if let Some(crawler) = recipes.recipe_for(&path, false)? {
+            children.push(crawler.parse_file(&path.to_string_lossy())?);
+        }
+    }
+    if children.is_empty() {
+        return Ok(None);
+    }
+    // Paths like "." have no name of their own
+    let name = dir
+        .canonicalize()
+        .ok()
+        .as_deref()
+        .unwrap_or(dir)
+        .file_name()
+        .map_or(dir.to_string_lossy(), |name| name.to_string_lossy())
+        .into_owned();
+    Ok(Some(Section::new(name, children, Vec::new())))
+}
+
+/// A recipe and the name it's picked by: its file name, minus the `.toml`
+pub struct Recipe {
+    pub name: String,
+    pub built_in: bool,
+    pub crawler: Crawler,
+}
+
+/// Every recipe that can be picked from, whether built in or from a directory
+pub struct Registry {
+    recipes: Vec<Recipe>,
+}
+
+impl Registry {
+    /// Loads the built in recipes, then every `*.toml` recipe in `dir` if it
+    /// exists. A recipe in `dir` replaces a built in one of the same name.
+    pub fn load(dir: Option<&Path>) -> Result<Self, CrawlError> {
+        let mut recipes = Vec::new();
+        for (name, toml_str) in BUILT_IN {
+            recipes.push(Recipe {
+                name: name.to_string(),
+                built_in: true,
+                crawler: Crawler::new(toml_str)?,
+            });
+        }
+        let entries = match dir.map(|dir| (dir, fs::read_dir(dir))) {
+            Some((_, Ok(entries))) => entries,
+            Some((dir, Err(source))) if source.kind() != ErrorKind::NotFound => {
+                return Err(CrawlError::Io {
+                    path: dir.into(),
+                    source,
+                })
+            }
+            _ => return Ok(Self { recipes }),
+        };
+        let mut paths: Vec<_> = entries
+            .filter_map(|entry| Some(entry.ok()?.path()))
+            .filter(|path| path.extension().is_some_and(|ext| ext == "toml"))
+            .collect();
+        paths.sort();
+        for path in paths {
+            let bad_recipe = |source| CrawlError::BadRecipe {
+                path: path.clone(),
+                source: Box::new(source),
+            };
+            let toml_str = fs::read_to_string(&path).map_err(|source| CrawlError::Io {
+                path: path.clone(),
+                source,
+            })?;
+            let crawler = Crawler::new(&toml_str).map_err(bad_recipe)?;
+            let name = path
+                .file_stem()
+                .map_or_else(String::new, |stem| stem.to_string_lossy().into_owned());
+            recipes.retain(|r| r.name != name);
+            recipes.push(Recipe {
+                name,
+                built_in: false,
+                crawler,
+            });
+        }
+        Ok(Self { recipes })
+    }
+
+    /// Looks a recipe up by name
+    pub fn get(&self, name: &str) -> Option<&Crawler> {
+        self.recipes
+            .iter()
+            .find(|r| r.name == name)
+            .map(|r| &r.crawler)
+    }
+
+    pub fn names(&self) -> Vec<&str> {
+        self.recipes.iter().map(|r| r.name.as_str()).collect()
+    }
+}
+
+impl Recipes for Registry {
+    /// Picks the one recipe for a file's extension. Recipes from the recipe
+    /// directory are picked over built in ones, but two recipes from the same
+    /// place that both fit are ambiguous.
+    fn recipe_for(&self, file: &Path, explicit: bool) -> Result<Option<&Crawler>, CrawlError> {
+        let fits = |r: &&Recipe| r.crawler.recipe_for(file, false).ok().flatten().is_some();
+        let mut candidates: Vec<_> = self.recipes.iter().filter(fits).collect();
+        if candidates.iter().any(|r| !r.built_in) {
+            candidates.retain(|r| !r.built_in);
+        }
+        match candidates.as_slice() {
+            [] if explicit => Err(CrawlError::NoRecipe { path: file.into() }),
+            [] => Ok(None),
+            [recipe] => Ok(Some(&recipe.crawler)),
+            _ => Err(CrawlError::AmbiguousRecipe {
+                path: file.into(),
+                names: candidates.iter().map(|r| r.name.clone()).collect(),
+            }),
+        }
+    }
+
+    fn exts(&self) -> Vec<String> {
+        let mut exts: Vec<_> = self.recipes.iter().flat_map(|r| r.crawler.exts()).collect();
+        exts.sort();
+        exts.dedup();
+        exts
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::{env, process};
+
+    fn recipe_dir(name: &str, recipes: &[(&str, &str)]) -> PathBuf {
+        let dir = env::temp_dir().join(format!("quizcrawler-{}-{}", name, process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        for (file, exts) in recipes {
+            let toml_str = format!("description = \"{}\"\nflow = \"Unflow\"\n{}\n", file, exts);
+            fs::write(dir.join(file), toml_str).unwrap();
+        }
+        dir
+    }
+
+    #[test]
+    fn built_in_recipes_by_extension() {
+        let registry = Registry::load(None).unwrap();
+        assert!(registry
+            .recipe_for(Path::new("notes.org"), false)
+            .unwrap()
+            .is_some());
+        assert!(registry
+            .recipe_for(Path::new("notes.pdf"), false)
+            .unwrap()
+            .is_none());
+        assert!(matches!(
+            registry.recipe_for(Path::new("notes.pdf"), true),
+            Err(CrawlError::NoRecipe { .. })
+        ));
+    }
+
+    #[test]
+    fn recipe_dir_comes_first() {
+        let dir = recipe_dir(
+            "first",
+            &[("mine.toml", r#"exts = ["org", "txt"]"#), ("notes.txt", "")],
+        );
+        let registry = Registry::load(Some(&dir)).unwrap();
+        fs::remove_dir_all(&dir).unwrap();
+        assert_eq!(registry.names(), vec!["borg", "markdown", "mine"]);
+        let picked = registry.recipe_for(Path::new("a.org"), false).unwrap();
+        assert!(std::ptr::eq(picked.unwrap(), registry.get("mine").unwrap()));
+        assert_eq!(registry.exts(), vec!["markdown", "md", "org", "txt"]);
+    }
+
+    #[test]
+    fn ambiguous_recipes() {
+        let dir = recipe_dir(
+            "ambiguous",
+            &[
+                ("a.toml", r#"exts = ["md"]"#),
+                ("b.toml", r#"exts = ["md"]"#),
+            ],
+        );
+        let registry = Registry::load(Some(&dir)).unwrap();
+        fs::remove_dir_all(&dir).unwrap();
+        match registry.recipe_for(Path::new("notes.md"), false) {
+            Err(CrawlError::AmbiguousRecipe { names, .. }) => assert_eq!(names, vec!["a", "b"]),
+            other => panic!("Expected an ambiguous recipe, got {:?}", other.err()),
+        }
+    }
+
+    #[test]
+    fn broken_recipe_is_named() {
+        let dir = recipe_dir("broken", &[("bad.toml", "exts = ")]);
+        let result = Registry::load(Some(&dir));
+        fs::remove_dir_all(&dir).unwrap();
+        match result {
+            Err(CrawlError::BadRecipe { path, .. }) => assert!(path.ends_with("bad.toml")),
+            _ => panic!("Expected a broken recipe"),
+        }
+    }
+}
@@ -1,24 +1,47 @@
 use super::data::ReflowStrategy;
 use onig::Regex;
+use std::sync::OnceLock;
+
+/// Compiles a regex that never changes, once for the whole run
+fn fixed_regex<'a>(cell: &'a OnceLock<Regex>, pattern: &str) -> &'a Regex {
+    cell.get_or_init(|| Regex::new(pattern).unwrap())
+}
 
 pub fn reflow_string(strategy: &ReflowStrategy, src: &str) -> String {
+    static LINE_BREAK: OnceLock<Regex> = OnceLock::new();
+    static INDENT: OnceLock<Regex> = OnceLock::new();
     match strategy {
-        ReflowStrategy::Unflow => Regex::new(r"\s*\n\s*")
-            .unwrap()
-            .replace_all(src.trim(), " "),
+        ReflowStrategy::Unflow => {
+            fixed_regex(&LINE_BREAK, r"\s*\n\s*").replace_all(src.trim(), " ")
+        }
         ReflowStrategy::Unindent => {
-            if let Some(indent) = Regex::new(r"\n\s*").unwrap().captures(src.trim()) {
-                Regex::new(indent.at(0).unwrap())
-                    .unwrap()
-                    .replace_all(src.trim(), "\n")
-            } else {
-                src.trim().to_string()
+            // The first line break sets the indent taken off of every line
+            let src = src.trim();
+            match fixed_regex(&INDENT, r"\n\s*").find(src) {
+                Some((start, end)) => src.replace(&src[start..end], "\n"),
+                None => src.to_string(),
             }
         }
         ReflowStrategy::Preserve => src.to_string(),
     }
 }
 
+/// Takes the indentation that every line of a block shares off of it, along
+/// with any blank lines around it
+pub fn dedent(src: &str) -> String {
+    let indent = src
+        .lines()
+        .filter(|l| !l.trim().is_empty())
+        .map(|l| l.len() - l.trim_start().len())
+        .min()
+        .unwrap_or(0);
+    let lines: Vec<_> = src
+        .lines()
+        .map(|l| l.get(indent..).unwrap_or("").trim_end())
+        .collect();
+    lines.join("\n").trim_matches('\n').to_string()
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -59,4 +82,10 @@ mod tests {
             DATA_STR.to_string()
         );
     }
+
+    #[test]
+    fn dedent_blocks() {
+        let block = "\n    fn main() {\n        go();\n\n    }\n  ";
+        assert_eq!(dedent(block), "fn main() {\n    go();\n\n}");
+    }
 }
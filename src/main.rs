@@ -1,8 +1,4 @@
-mod console;
-mod core;
-mod crawler;
-
-use crate::console::cli;
+use quizcrawler::console::cli;
 
 fn main() {
     if let Err(e) = cli::run() {
@@ -1,8 +1,13 @@
 use insta::assert_ron_snapshot;
-use quizcrawler::crawler::data::Crawler;
-use std::fs;
+use quizcrawler::core::data::Question;
+use quizcrawler::crawler::{
+    data::{CrawlError, Crawler},
+    registry::Recipes,
+};
+use std::{fs, rc::Rc};
 
 const CONF_FILE: &str = "confs/borg.toml";
+const MARKDOWN_CONF_FILE: &str = "confs/markdown.toml";
 
 fn crawler() -> Crawler {
     let conf_str = fs::read_to_string(CONF_FILE).unwrap();
@@ -14,6 +19,10 @@ fn crawler() -> Crawler {
     })
 }
 
+fn markdown_crawler() -> Crawler {
+    Crawler::new(&fs::read_to_string(MARKDOWN_CONF_FILE).unwrap()).unwrap()
+}
+
 #[test]
 fn test_parse_terms() {
     let data_str = r#"
@@ -22,7 +31,7 @@ fn test_parse_terms() {
   - wissen / weiß / hat gewusst :: to know
   - in der Zwischenzeit :: [in the] meantime"#;
 
-    let section = crawler().parse_sections(data_str);
+    let section = crawler().parse_sections(data_str).unwrap();
     assert_ron_snapshot!(section, {".**.last_correct" => "[last_correct]", ".**.id" => "[id]"});
 }
 
@@ -35,7 +44,7 @@ fn test_parse_lists() {
   2) This, for example, happens after the first point.
   3) Or perhaps this is the third most expensive solution."#;
 
-    let section = crawler().parse_sections(data_str);
+    let section = crawler().parse_sections(data_str).unwrap();
     assert_ron_snapshot!(section, {".**.last_correct" => "[last_correct]", ".**.id" => "[id]"});
 }
 
@@ -47,7 +56,7 @@ fn test_parse_bullets() {
   - And another one! Only use these when there is no better option.
   - Definitions and processes have their own structures."#;
 
-    let section = crawler().parse_sections(data_str);
+    let section = crawler().parse_sections(data_str).unwrap();
     assert_ron_snapshot!(section, {".**.last_correct" => "[last_correct]", ".**.id" => "[id]"});
 }
 
@@ -62,12 +71,463 @@ fn test_parse_multiline_terms() {
     engagement and recall.
 "#;
 
-    let section = crawler().parse_sections(data_str);
+    let section = crawler().parse_sections(data_str).unwrap();
+    assert_ron_snapshot!(section, {".**.last_correct" => "[last_correct]", ".**.id" => "[id]"});
+}
+
+#[test]
+fn test_parse_clozes() {
+    let data_str = r#"
+* Terms
+  - The {{c1::mitochondria}} is the {{c2::powerhouse}} of the {{c1::cell}}.
+  - Cloze :: A card with {{c1::blanks}} to fill in"#;
+
+    let section = crawler().parse_sections(data_str).unwrap();
+    assert_ron_snapshot!(section, {".**.last_correct" => "[last_correct]", ".**.id" => "[id]"});
+}
+
+#[test]
+fn test_parse_repeated_lines() {
+    let data_str = r#"
+* Terms
+  - a :: b
+  - a :: b
+    and - more"#;
+
+    let section = crawler().parse_sections(data_str).unwrap();
     assert_ron_snapshot!(section, {".**.last_correct" => "[last_correct]", ".**.id" => "[id]"});
 }
 
 #[test]
 fn test_parse_file() {
-    let section = crawler().parse_file("tests/data/borg.org");
+    let section = crawler().parse_file("tests/data/borg.org").unwrap();
     assert_ron_snapshot!(section, {".**.last_correct" => "[last_correct]", ".**.id" => "[id]"});
 }
+
+#[test]
+fn test_bad_recipe_regex() {
+    let conf_str = fs::read_to_string(CONF_FILE)
+        .unwrap()
+        .replace(r#"separator = " :: ""#, r#"separator = " :: (""#);
+    match Crawler::new(&conf_str) {
+        Err(CrawlError::Regex { field, .. }) => assert_eq!(field, "term.separator"),
+        other => panic!("Expected a regex error, got {:?}", other.err()),
+    }
+}
+
+#[test]
+fn test_bad_section_regex() {
+    // The marker doesn't compile on its own, but it isn't to blame
+    let conf_str = fs::read_to_string(CONF_FILE).unwrap().replacen(
+        r#"body = "[\\s\\S]*?""#,
+        r#"body = "[\\s\\S]*?(""#,
+        1,
+    );
+    match Crawler::new(&conf_str) {
+        Err(CrawlError::Regex { field, .. }) => assert_eq!(field, "section.body"),
+        other => panic!("Expected a regex error, got {:?}", other.err()),
+    }
+}
+
+#[test]
+fn test_missing_notes() {
+    match crawler().parse_file("tests/data/missing.org") {
+        Err(CrawlError::Io { path, .. }) => assert!(path.ends_with("missing.org")),
+        other => panic!("Expected an IO error, got {:?}", other.err()),
+    }
+}
+
+#[test]
+fn test_coverage() {
+    let data_str = "* Terms\n  Stray text\n  - Term :: Definition\n  - A fact\n  1) A step";
+    let coverage = crawler().coverage(data_str).unwrap();
+    let rules: Vec<_> = coverage
+        .iter()
+        .map(|c| (c.rule, data_str[c.range.clone()].trim()))
+        .collect();
+    assert_eq!(
+        rules,
+        vec![
+            ("section", "* Terms"),
+            ("term", "- Term :: Definition"),
+            ("bullet", "- A fact"),
+            ("list", "1) A step"),
+        ]
+    );
+}
+
+#[test]
+fn test_source_locations() {
+    let data_str = "* Terms\n  - Term :: Definition\n** Nested\n   - A fact\n   1) A step\n      - Its detail\n";
+    let sections = crawler().parse_sections(data_str).unwrap();
+    let terms = &sections[0];
+    let location = |q: &quizcrawler::core::data::QuestionRef| q.borrow().location.clone().unwrap();
+    assert_eq!(
+        terms.location.as_ref().unwrap().to_string(),
+        "line 1:1-6:18"
+    );
+    assert_eq!(location(&terms.questions[0]).to_string(), "line 2:3-2:22");
+    let nested = &terms.children[0];
+    assert_eq!(nested.location.as_ref().unwrap().start, (3, 1));
+    let spans: Vec<_> = nested
+        .questions
+        .iter()
+        .map(|q| (location(q).start, location(q).end))
+        .collect();
+    assert_eq!(spans, vec![((5, 4), (6, 18)), ((4, 4), (4, 11))]);
+}
+
+#[test]
+fn test_parse_directory() {
+    let root = std::env::temp_dir().join(format!("quizcrawler-course-{}", std::process::id()));
+    for dir in &["Week 1", "Empty", ".git"] {
+        fs::create_dir_all(root.join(dir)).unwrap();
+    }
+    let notes = "* Terms\n  - Term :: Definition\n";
+    for file in &[
+        "Week 1/Monday.org",
+        "Week 1/Tuesday.org",
+        "Intro.org",
+        ".git/HEAD.org",
+    ] {
+        fs::write(root.join(file), notes).unwrap();
+    }
+    fs::write(root.join("Week 1/Reading.txt"), notes).unwrap();
+
+    let tree = crawler().parse_path(&root.to_string_lossy()).unwrap();
+    fn names(section: &quizcrawler::core::data::Section) -> String {
+        let children: Vec<_> = section.children.iter().map(names).collect();
+        format!("{}[{}]", section.name, children.join(", "))
+    }
+    let course = root.file_name().unwrap().to_string_lossy();
+    assert_eq!(
+        names(&tree),
+        format!(
+            "{}[Intro.org[Terms[]], Week 1[Monday.org[Terms[]], Tuesday.org[Terms[]]]]",
+            course
+        )
+    );
+    // Every file's questions get their own ids and know where they came from
+    let term = |path: &[&str]| Rc::clone(&tree.child_at_path(path).unwrap().questions[0]);
+    let (monday, tuesday) = (
+        term(&["Week 1", "Monday.org", "Terms"]),
+        term(&["Week 1", "Tuesday.org", "Terms"]),
+    );
+    assert_ne!(monday.borrow().id, tuesday.borrow().id);
+    // Ids are stamped once, with every folder and file in their paths
+    let path = [course.as_ref(), "Week 1", "Monday.org", "Terms"];
+    let key = monday.borrow().data.key();
+    assert_eq!(
+        monday.borrow().id,
+        Question::derive_id(&path, &format!("{}#1", key))
+    );
+    let file = monday.borrow().location.clone().unwrap().file.unwrap();
+    assert!(file.ends_with("Monday.org"));
+
+    match crawler().parse_path(&root.join("Empty").to_string_lossy()) {
+        Err(CrawlError::NoNotes { exts, .. }) => assert_eq!(exts, vec!["org"]),
+        other => panic!("Expected no notes to be found, got {:?}", other.err()),
+    }
+    fs::remove_dir_all(&root).unwrap();
+}
+
+#[cfg(unix)]
+#[test]
+fn test_parse_directory_symlink_loop() {
+    let root = std::env::temp_dir().join(format!("quizcrawler-loop-{}", std::process::id()));
+    fs::create_dir_all(root.join("Week 1")).unwrap();
+    fs::write(
+        root.join("Week 1/Monday.org"),
+        "* Terms\n  - Term :: Definition\n",
+    )
+    .unwrap();
+    std::os::unix::fs::symlink(&root, root.join("Week 1/Back")).unwrap();
+    let tree = crawler().parse_path(&root.to_string_lossy());
+    fs::remove_dir_all(&root).unwrap();
+    let tree = tree.unwrap();
+    assert_eq!(tree.children.len(), 1);
+    assert_eq!(tree.children[0].children.len(), 1);
+}
+
+#[test]
+fn test_parse_title() {
+    let dir = std::env::temp_dir().join(format!("quizcrawler-title-{}", std::process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    let (titled, untitled) = (dir.join("titled.org"), dir.join("untitled.org"));
+    let notes = "* Terms\n  - Term :: Definition\n";
+    let header = "#+TITLE: Organic Chemistry\n#+author: A. Student\n#+DATE: 2021-03-01\n";
+    fs::write(&titled, format!("{}{}", header, notes)).unwrap();
+    fs::write(&untitled, notes).unwrap();
+    let titled = crawler().parse_file(&titled.to_string_lossy()).unwrap();
+    let untitled = crawler().parse_file(&untitled.to_string_lossy()).unwrap();
+    fs::remove_dir_all(&dir).unwrap();
+
+    // The title is only shown, the file name still keys the saved progress
+    assert_eq!(titled.name, "titled.org");
+    assert_eq!(titled.title(), "Organic Chemistry");
+    let metadata = titled.metadata.unwrap();
+    assert_eq!(metadata.author.as_deref(), Some("A. Student"));
+    assert_eq!(metadata.date.as_deref(), Some("2021-03-01"));
+    assert_eq!(titled.children[0].name, "Terms");
+    assert_eq!(untitled.name, "untitled.org");
+    assert_eq!(untitled.title(), "untitled.org");
+    assert_eq!(untitled.metadata.unwrap().author, None);
+}
+
+#[test]
+fn test_legacy_recipe_title() {
+    // Recipes used to describe themselves with a `title` string
+    let conf_str = fs::read_to_string(CONF_FILE)
+        .unwrap()
+        .replace("description = ", "title = ");
+    let conf_str = conf_str[..conf_str.find("[title]").unwrap()].to_string()
+        + &conf_str[conf_str.find("# Sections").unwrap()..];
+    let crawler = Crawler::new(&conf_str).unwrap();
+    assert!(crawler.title.is_none());
+}
+
+#[test]
+fn test_markdown_terms() {
+    let data_str = r#"
+# Terms
+- der / die Lehrer(in) :: teacher
+- **wissen / weiß / hat gewusst**: to know
+**in der Zwischenzeit**: [in the] meantime"#;
+
+    let section = markdown_crawler().parse_sections(data_str).unwrap();
+    assert_ron_snapshot!(section, {".**.last_correct" => "[last_correct]", ".**.id" => "[id]"});
+}
+
+#[test]
+fn test_markdown_lists() {
+    let data_str = r#"
+# Terms
+1. Use numbers to indicate some sort of process or ranking.
+   - This is an annotation about this step in the process.
+2. This, for example, happens after the first point.
+3. Or perhaps this is the third most expensive solution."#;
+
+    let section = markdown_crawler().parse_sections(data_str).unwrap();
+    assert_ron_snapshot!(section, {".**.last_correct" => "[last_correct]", ".**.id" => "[id]"});
+}
+
+#[test]
+fn test_markdown_bullets() {
+    let data_str = r#"
+# Terms
+- Here is some short, relevant fact regarding this subtopic.
+* And another one! Only use these when there is no better option.
+- Definitions and processes have their own structures."#;
+
+    let section = markdown_crawler().parse_sections(data_str).unwrap();
+    assert_ron_snapshot!(section, {".**.last_correct" => "[last_correct]", ".**.id" => "[id]"});
+}
+
+#[test]
+fn test_markdown_multiline_terms() {
+    let data_str = r#"
+# Terms
+- Quizcrawler :: Quizcrawler is an application that, when fed a file of
+  class-notes, crawls the structure and generates interactive quizzes that can be
+  used as review. It leverages spaced repetition and forced / active recall to
+  enhance learning. The gamification of studying should further increase
+  engagement and recall.
+"#;
+
+    let section = markdown_crawler().parse_sections(data_str).unwrap();
+    assert_ron_snapshot!(section, {".**.last_correct" => "[last_correct]", ".**.id" => "[id]"});
+}
+
+#[test]
+fn test_markdown_sections() {
+    let data_str = r#"
+# Chemistry
+- Atoms are mostly empty space.
+
+## Bonds
+- **Covalent bond**: A bond made by sharing electrons
+
+# History
+1. The first step
+
+We met in 2021. Then we parted.
+"#;
+
+    let section = markdown_crawler().parse_sections(data_str).unwrap();
+    assert_ron_snapshot!(section, {".**.last_correct" => "[last_correct]", ".**.id" => "[id]"});
+}
+
+#[test]
+fn test_parse_excluded() {
+    let data_str = r#"
+* Terms
+  :PROPERTIES:
+  - id :: not a term
+  :END:
+  - Kept :: A real term
+  #+BEGIN_EXAMPLE
+  - x :: y
+  #+END_EXAMPLE
+  # - Commented :: out
+  - A real fact"#;
+
+    let section = crawler().parse_sections(data_str).unwrap();
+    assert_ron_snapshot!(section, {".**.last_correct" => "[last_correct]", ".**.id" => "[id]"});
+}
+
+#[test]
+fn test_markdown_excluded() {
+    let data_str = "# Notes\n<!--\n- not :: a term\n-->\n- A fact\n<!-- - hidden -->\n";
+    let sections = markdown_crawler().parse_sections(data_str).unwrap();
+    let questions = &sections[0].questions;
+    assert_eq!(questions.len(), 1);
+    let question = questions[0].borrow();
+    assert_eq!(question.ask(), "Name a fact from this section");
+    assert_eq!(question.peek(), "A fact");
+    // The fact is still found where it is in the notes
+    let location = question.location.clone().unwrap();
+    assert_eq!((location.start, location.end), ((5, 1), (5, 8)));
+    let rules: Vec<_> = markdown_crawler()
+        .coverage(data_str)
+        .unwrap()
+        .into_iter()
+        .map(|c| c.rule)
+        .collect();
+    assert_eq!(
+        rules,
+        vec!["title", "section", "excluded", "bullet", "excluded"]
+    );
+}
+
+#[test]
+fn test_parse_code() {
+    let data_str = r#"
+* Python
+  #+CAPTION: Print a greeting
+  #+BEGIN_SRC python :results output
+  def greet(name):
+      print(f"Hello {name}")
+  #+END_SRC
+  - A fact
+  #+begin_src shell
+  ls -la
+  # * Not a heading
+  #+end_src
+  - Kept :: A real term
+  - Sorting a list in place:
+    #+BEGIN_SRC python
+    xs.sort()
+    #+END_SRC"#;
+
+    let section = crawler().parse_sections(data_str).unwrap();
+    assert_ron_snapshot!(section, {".**.last_correct" => "[last_correct]", ".**.id" => "[id]"});
+}
+
+#[test]
+fn test_excluded_code() {
+    let data_str = r#"
+* Python
+  :PROPERTIES:
+  #+BEGIN_SRC python
+  hidden()
+  #+END_SRC
+  :END:
+  #+BEGIN_COMMENT
+  #+BEGIN_SRC python
+  commented()
+  #+END_SRC
+  #+END_COMMENT
+  #+BEGIN_SRC python
+  kept()
+  #+END_SRC"#;
+    let sections = crawler().parse_sections(data_str).unwrap();
+    let questions: Vec<_> = sections[0]
+        .questions
+        .iter()
+        .map(|q| q.borrow().peek().to_owned())
+        .collect();
+    assert_eq!(questions, ["kept()"]);
+
+    let data_str = "# Rust\n<!--\n```rust\nhidden();\n```\n-->\n```rust\nkept();\n```\n";
+    let questions = markdown_crawler().parse_questions(data_str).unwrap();
+    let questions: Vec<_> = questions.iter().map(|q| q.peek().to_owned()).collect();
+    assert_eq!(questions, ["kept();"]);
+}
+
+#[test]
+fn test_markdown_code() {
+    let data_str = r#"# Rust
+Print a greeting:
+```rust
+fn main() {
+    println!("hi");
+}
+```
+- A fact
+
+~~~
+# not a heading
+~~~
+"#;
+    let sections = markdown_crawler().parse_sections(data_str).unwrap();
+    assert_eq!(sections.len(), 1);
+    let questions: Vec<_> = sections[0]
+        .questions
+        .iter()
+        .map(|q| (q.borrow().ask().into_owned(), q.borrow().peek().to_owned()))
+        .collect();
+    let expected = [
+        ("Name a fact from this section", "A fact"),
+        ("Print a greeting:", "fn main() {\n    println!(\"hi\");\n}"),
+        ("Write out the snippet from this section", "# not a heading"),
+    ];
+    let expected: Vec<_> = expected
+        .iter()
+        .map(|(q, a)| (q.to_string(), a.to_string()))
+        .collect();
+    assert_eq!(questions, expected);
+    let location = sections[0].questions[1].borrow().location.clone().unwrap();
+    assert_eq!((location.start, location.end), ((2, 1), (7, 3)));
+}
+
+#[test]
+fn test_parse_tables() {
+    let data_str = r#"
+* Articles
+  - A fact
+  | Case       | Masculine | Plural |
+  |------------+-----------+--------|
+  | Nominative | der       | die    |
+  | Dative     | dem       |        |
+  - Another fact"#;
+
+    let section = crawler().parse_sections(data_str).unwrap();
+    assert_ron_snapshot!(section, {".**.last_correct" => "[last_correct]", ".**.id" => "[id]"});
+}
+
+#[test]
+fn test_markdown_tables() {
+    let data_str =
+        "# German\n| Case | Plural |\n| :--- | :----: |\n| Dative | den |\n| Genitive | der\n";
+    let sections = markdown_crawler().parse_sections(data_str).unwrap();
+    let questions: Vec<_> = sections[0]
+        .questions
+        .iter()
+        .map(|q| (q.borrow().ask().into_owned(), q.borrow().peek().to_owned()))
+        .collect();
+    let expected = [("Dative, Plural?", "den"), ("Genitive, Plural?", "der")];
+    let expected: Vec<_> = expected
+        .iter()
+        .map(|(q, a)| (q.to_string(), a.to_string()))
+        .collect();
+    assert_eq!(questions, expected);
+    let location = sections[0].questions[1].borrow().location.clone().unwrap();
+    assert_eq!((location.start, location.end), ((5, 1), (5, 16)));
+    let rules: Vec<_> = markdown_crawler()
+        .coverage(data_str)
+        .unwrap()
+        .into_iter()
+        .map(|c| c.rule)
+        .collect();
+    assert_eq!(rules, vec!["title", "section", "table"]);
+}